@@ -0,0 +1,35 @@
+use Buffer;
+use History;
+
+/// Suggests how the line being edited could continue. The rest of the suggestion is shown after
+/// the buffer, and can be accepted with `Editor::accept_autosuggestion()`.
+///
+/// The default is `HistoryAutosuggestions`.
+pub trait AutosuggestionProvider {
+    /// Returns the suggested line for `buf`. It is only shown if it starts with `buf` and is
+    /// longer. `history_index` is the index of the history entry being edited, if any.
+    fn suggest(&self, buf: &Buffer, history: &History, history_index: Option<usize>)
+        -> Option<Buffer>;
+}
+
+impl<F> AutosuggestionProvider for F
+where
+    F: Fn(&Buffer, &History, Option<usize>) -> Option<Buffer>,
+{
+    fn suggest(&self, buf: &Buffer, history: &History, history_index: Option<usize>)
+        -> Option<Buffer> {
+        self(buf, history, history_index)
+    }
+}
+
+/// Suggests the newest history entry which starts with the buffer, older than the entry being
+/// edited.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HistoryAutosuggestions;
+
+impl AutosuggestionProvider for HistoryAutosuggestions {
+    fn suggest(&self, buf: &Buffer, history: &History, history_index: Option<usize>)
+        -> Option<Buffer> {
+        history.get_newest_match(history_index, buf).cloned()
+    }
+}
@@ -307,6 +307,28 @@ impl Buffer {
         }
     }
 
+    /// Returns the index of the first char of the first occurrence of `pattern` in this buffer.
+    /// An empty pattern matches at index 0.
+    pub fn find(&self, pattern: &[char]) -> Option<usize> {
+        self.find_from(pattern, 0)
+    }
+
+    /// Returns the index of the first char of the first occurrence of `pattern` which starts at
+    /// or after `start`.
+    pub fn find_from(&self, pattern: &[char], start: usize) -> Option<usize> {
+        if start > self.data.len() {
+            return None;
+        }
+        if pattern.is_empty() {
+            return Some(start);
+        }
+
+        self.data[start..]
+            .windows(pattern.len())
+            .position(|w| w == pattern)
+            .map(|i| start + i)
+    }
+
     /// Return true if the buffer is empty.
     pub fn is_empty(&self) -> bool {
         self.data.is_empty()
@@ -477,6 +499,15 @@ mod tests {
         assert_eq!(buf.starts_with(&buf2), false);
     }
 
+    #[test]
+    fn test_find() {
+        let buf = Buffer::from("abcabc");
+        assert_eq!(buf.find(&['b', 'c']), Some(1));
+        assert_eq!(buf.find(&['c', 'a']), Some(2));
+        assert_eq!(buf.find(&['x']), None);
+        assert_eq!(buf.find(&[]), Some(0));
+    }
+
     #[test]
     fn test_print_rest() {
         let mut buf = Buffer::new();
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+
+use Completer;
+use Completion;
+use CompletionContext;
+use LineCompletions;
+
+/// Offers the completions of all of its completers.
+///
+/// ```
+/// use liner::*;
+///
+/// let completer = UnionCompleter::new()
+///     .with(BasicCompleter::new(vec!["build"]))
+///     .with(BasicCompleter::new(vec!["bench"]));
+/// assert_eq!(completer.completions("b"), vec!["build", "bench"]);
+/// ```
+#[derive(Default)]
+pub struct UnionCompleter {
+    completers: Vec<Box<Completer>>,
+}
+
+impl UnionCompleter {
+    pub fn new() -> Self {
+        UnionCompleter::default()
+    }
+
+    pub fn with<C: Completer + 'static>(mut self, completer: C) -> Self {
+        self.completers.push(Box::new(completer));
+        self
+    }
+}
+
+impl Completer for UnionCompleter {
+    fn rich_completions(&self, start: &str) -> Vec<Completion> {
+        self.completers
+            .iter()
+            .flat_map(|c| c.rich_completions(start))
+            .collect()
+    }
+
+    /// Completes the range of the first completer with any completions. Completions of other
+    /// ranges are left out, because only one range can be replaced.
+    fn complete_line(&self, line: &CompletionContext) -> LineCompletions {
+        let mut res: Option<LineCompletions> = None;
+        for completer in &self.completers {
+            let mut next = completer.complete_line(line);
+            match res {
+                Some(ref mut res) if res.start == next.start && res.end == next.end => {
+                    res.completions.append(&mut next.completions)
+                }
+                Some(_) => {}
+                None if !next.completions.is_empty() => res = Some(next),
+                None => {}
+            }
+        }
+        res.unwrap_or_default()
+    }
+}
+
+/// Chooses a completer based on the position of the word being completed: the word before it,
+/// or its index. For example, a shell could complete commands for the first word, directories
+/// after `cd`, and filenames otherwise:
+///
+/// ```
+/// use liner::*;
+///
+/// let completer = PositionCompleter::new()
+///     .with_index(0, CommandCompleter::new())
+///     .after_word("cd", FilenameCompleter::new(None::<String>).with_dirs_only(true))
+///     .with_default(FilenameCompleter::new(None::<String>));
+/// ```
+#[derive(Default)]
+pub struct PositionCompleter {
+    by_index: HashMap<usize, Box<Completer>>,
+    by_previous_word: HashMap<String, Box<Completer>>,
+    default: Option<Box<Completer>>,
+}
+
+impl PositionCompleter {
+    pub fn new() -> Self {
+        PositionCompleter::default()
+    }
+
+    /// Completes the word with index `index`, counting from 0, with `completer`.
+    pub fn with_index<C: Completer + 'static>(mut self, index: usize, completer: C) -> Self {
+        self.by_index.insert(index, Box::new(completer));
+        self
+    }
+
+    /// Completes the word after `word` with `completer`. This takes priority over
+    /// `with_index()`.
+    pub fn after_word<S, C>(mut self, word: S, completer: C) -> Self
+    where
+        S: Into<String>,
+        C: Completer + 'static,
+    {
+        self.by_previous_word.insert(word.into(), Box::new(completer));
+        self
+    }
+
+    /// Completes the words which no other completer is chosen for with `completer`.
+    pub fn with_default<C: Completer + 'static>(mut self, completer: C) -> Self {
+        self.default = Some(Box::new(completer));
+        self
+    }
+
+    /// The completer chosen for the line.
+    fn completer(&self, line: &CompletionContext) -> Option<&Completer> {
+        let index = line.word_index();
+        let previous = if index > 0 { line.nth_word(index - 1) } else { None };
+
+        previous
+            .and_then(|word| self.by_previous_word.get(&word))
+            .or_else(|| self.by_index.get(&index))
+            .or(self.default.as_ref())
+            .map(|c| &**c)
+    }
+}
+
+impl Completer for PositionCompleter {
+    /// Completes `start` as the first word of a line, since there is no line to choose a
+    /// completer by.
+    fn rich_completions(&self, start: &str) -> Vec<Completion> {
+        match self.by_index.get(&0).or(self.default.as_ref()) {
+            Some(completer) => completer.rich_completions(start),
+            None => Vec::new(),
+        }
+    }
+
+    fn complete_line(&self, line: &CompletionContext) -> LineCompletions {
+        match self.completer(line) {
+            Some(completer) => completer.complete_line(line),
+            None => LineCompletions::default(),
+        }
+    }
+}
+
+/// Chooses a completer based on how the word being completed starts, such as `$` for variables or
+/// `-` for flags. The completer with the longest matching prefix is chosen, and completes the
+/// whole word, including the prefix.
+///
+/// ```
+/// use liner::*;
+///
+/// let completer = PrefixCompleter::new()
+///     .with_prefix("-", BasicCompleter::new(vec!["-h"]))
+///     .with_prefix("--", BasicCompleter::new(vec!["--help", "--version"]))
+///     .with_default(BasicCompleter::new(vec!["help"]));
+/// assert_eq!(completer.completions("--"), vec!["--help", "--version"]);
+/// assert_eq!(completer.completions("h"), vec!["help"]);
+/// ```
+#[derive(Default)]
+pub struct PrefixCompleter {
+    by_prefix: Vec<(String, Box<Completer>)>,
+    default: Option<Box<Completer>>,
+}
+
+impl PrefixCompleter {
+    pub fn new() -> Self {
+        PrefixCompleter::default()
+    }
+
+    /// Completes words starting with `prefix` with `completer`.
+    pub fn with_prefix<S, C>(mut self, prefix: S, completer: C) -> Self
+    where
+        S: Into<String>,
+        C: Completer + 'static,
+    {
+        self.by_prefix.push((prefix.into(), Box::new(completer)));
+        self
+    }
+
+    /// Completes the words without any of the prefixes with `completer`.
+    pub fn with_default<C: Completer + 'static>(mut self, completer: C) -> Self {
+        self.default = Some(Box::new(completer));
+        self
+    }
+
+    /// The completer chosen for `word`.
+    fn completer(&self, word: &str) -> Option<&Completer> {
+        self.by_prefix
+            .iter()
+            .filter(|entry| word.starts_with(&entry.0[..]))
+            .max_by_key(|entry| entry.0.len())
+            .map(|entry| &entry.1)
+            .or(self.default.as_ref())
+            .map(|c| &**c)
+    }
+}
+
+impl Completer for PrefixCompleter {
+    fn rich_completions(&self, start: &str) -> Vec<Completion> {
+        match self.completer(start) {
+            Some(completer) => completer.rich_completions(start),
+            None => Vec::new(),
+        }
+    }
+
+    fn complete_line(&self, line: &CompletionContext) -> LineCompletions {
+        match self.completer(&line.word()) {
+            Some(completer) => completer.complete_line(line),
+            None => LineCompletions::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use context::get_buffer_words;
+    use Buffer;
+    use BasicCompleter;
+    use CursorPosition;
+
+    fn complete(completer: &Completer, text: &str) -> LineCompletions {
+        let buf = Buffer::from(text);
+        let words = get_buffer_words(&buf);
+        let position = CursorPosition::get(buf.num_chars(), &words);
+        completer.complete_line(&CompletionContext::new(&buf, buf.num_chars(), &words, position))
+    }
+
+    fn texts(completions: LineCompletions) -> Vec<String> {
+        completions.completions.into_iter().map(|c| c.text).collect()
+    }
+
+    /// Completes `$` variables, replacing only the name after the `$`.
+    struct VariableCompleter;
+
+    impl Completer for VariableCompleter {
+        fn rich_completions(&self, _: &str) -> Vec<Completion> {
+            Vec::new()
+        }
+
+        fn complete_line(&self, line: &CompletionContext) -> LineCompletions {
+            LineCompletions {
+                start: line.word_start() + 1,
+                end: line.cursor,
+                completions: vec![Completion::new("HOME")],
+                hint: None,
+            }
+        }
+    }
+
+    #[test]
+    fn union() {
+        let completer = UnionCompleter::new()
+            .with(BasicCompleter::new(vec!["cargo"]))
+            .with(VariableCompleter)
+            .with(BasicCompleter::new(vec!["cat", "cd"]));
+
+        let res = complete(&completer, "echo ca");
+        assert_eq!((res.start, res.end), (5, 7));
+        assert_eq!(texts(res), vec!["cargo", "cat"]);
+
+        let res = complete(&completer, "echo $");
+        assert_eq!((res.start, res.end), (6, 6));
+        assert_eq!(texts(res), vec!["HOME"]);
+    }
+
+    #[test]
+    fn position() {
+        let completer = PositionCompleter::new()
+            .with_index(0, BasicCompleter::new(vec!["cd", "cat"]))
+            .with_index(2, BasicCompleter::new(vec!["third"]))
+            .after_word("cd", BasicCompleter::new(vec!["dir"]))
+            .with_default(BasicCompleter::new(vec!["file"]));
+
+        assert_eq!(texts(complete(&completer, "c")), vec!["cat", "cd"]);
+        assert_eq!(texts(complete(&completer, "cd ")), vec!["dir"]);
+        assert_eq!(texts(complete(&completer, "cat ")), vec!["file"]);
+        assert_eq!(texts(complete(&completer, "cat a ")), vec!["third"]);
+        assert_eq!(texts(complete(&completer, "cat a cd ")), vec!["dir"]);
+        assert_eq!(texts(complete(&completer, "cat a b ")), vec!["file"]);
+        assert_eq!(texts(complete(&PositionCompleter::new(), "cat ")), Vec::<String>::new());
+
+        assert_eq!(completer.completions("c"), vec!["cat", "cd"]);
+        let defaults = PositionCompleter::new().with_default(BasicCompleter::new(vec!["file"]));
+        assert_eq!(defaults.completions("f"), vec!["file"]);
+        assert_eq!(PositionCompleter::new().completions("c"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn prefix() {
+        let completer = PrefixCompleter::new()
+            .with_prefix("$", BasicCompleter::new(vec!["$HOME", "$PATH"]))
+            .with_prefix("-", BasicCompleter::new(vec!["-h"]))
+            .with_prefix("--", BasicCompleter::new(vec!["--help"]));
+
+        assert_eq!(texts(complete(&completer, "ls $")), vec!["$HOME", "$PATH"]);
+        assert_eq!(texts(complete(&completer, "ls -")), vec!["-h"]);
+        assert_eq!(texts(complete(&completer, "ls --")), vec!["--help"]);
+        assert_eq!(texts(complete(&completer, "ls ")), Vec::<String>::new());
+    }
+}
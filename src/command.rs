@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use Editor;
+use MenuMove;
+use SearchDirection;
+
+/// A named editor command. The editor's output is a trait object, so that a command can be used
+/// with any `Editor`.
+pub type Command = for<'a, 'w> Fn(&mut Editor<'a, Write + 'w>) -> io::Result<()>;
+
+/// The named commands which can be run with `Editor::run_command()`, and which keys can be bound
+/// to in `Context::key_binding_table`.
+///
+/// The registry starts out with the built-in editing commands, such as `"kill-line"` and
+/// `"previous-history"`. Commands can be added or replaced with `register()`.
+///
+/// ```
+/// extern crate liner;
+/// extern crate termion;
+///
+/// use liner::*;
+/// use termion::event::Key;
+///
+/// # fn main() {
+/// let mut context = Context::new();
+/// context.commands.register("insert-date", |ed| ed.insert_str_after_cursor("2017-01-01"));
+/// context.key_binding_table.bind(BindingMode::Emacs, &[Key::Ctrl('t')], "insert-date");
+/// # }
+/// ```
+///
+/// Some commands, such as `"accept-line"` and `"complete"`, are handled by the keymap instead,
+/// and are not in the registry.
+pub struct CommandRegistry {
+    commands: HashMap<String, Rc<Command>>,
+}
+
+impl CommandRegistry {
+    /// Creates a registry with the built-in commands.
+    pub fn new() -> Self {
+        let mut reg = CommandRegistry::empty();
+
+        reg.register("clear-screen", |ed| ed.clear());
+        reg.register("beginning-of-line", |ed| ed.move_cursor_to_start_of_line());
+        reg.register("end-of-line", |ed| ed.move_cursor_to_end_of_line());
+        reg.register("backward-char", |ed| ed.move_cursor_left(1));
+        reg.register("forward-char", |ed| {
+            if ed.is_currently_showing_autosuggestion() && ed.cursor_is_at_end_of_line() {
+                ed.accept_autosuggestion()
+            } else {
+                ed.move_cursor_right(1)
+            }
+        });
+        reg.register("accept-autosuggestion", |ed| {
+            if ed.is_currently_showing_autosuggestion() {
+                ed.accept_autosuggestion()
+            } else {
+                ed.move_cursor_right(1)
+            }
+        });
+        reg.register("accept-autosuggestion-word", |ed| ed.accept_autosuggestion_word());
+        reg.register("accept-autosuggestion-char", |ed| ed.accept_autosuggestion_char());
+        reg.register("delete-char", |ed| ed.delete_after_cursor());
+        reg.register("backward-delete-char", |ed| ed.delete_before_cursor());
+        reg.register("previous-history", |ed| ed.move_up());
+        reg.register("next-history", |ed| ed.move_down());
+        reg.register("beginning-of-history", |ed| ed.move_to_start_of_history());
+        reg.register("end-of-history", |ed| ed.move_to_end_of_history());
+        reg.register("unix-line-discard", |ed| ed.delete_all_before_cursor());
+        reg.register("kill-line", |ed| ed.delete_all_after_cursor());
+        reg.register("kill-whole-line", |ed| {
+            ed.move_cursor_to_end_of_line()?;
+            ed.delete_all_before_cursor()
+        });
+        reg.register("unix-word-rubout", |ed| ed.delete_word_before_cursor(true));
+        reg.register("backward-kill-word", |ed| ed.delete_word_before_cursor(true));
+        reg.register("yank", |ed| ed.yank());
+        reg.register("yank-pop", |ed| ed.yank_pop());
+        reg.register("history-search-backward", |ed| {
+            ed.search_history_prefix(SearchDirection::Backward)
+        });
+        reg.register("history-search-forward", |ed| {
+            ed.search_history_prefix(SearchDirection::Forward)
+        });
+        reg.register("reverse-search-history", |ed| ed.search(SearchDirection::Backward));
+        reg.register("forward-search-history", |ed| ed.search(SearchDirection::Forward));
+        reg.register("abort", |ed| if ed.is_searching() {
+            ed.abort_search()
+        } else {
+            Ok(())
+        });
+        reg.register("menu-next", |ed| ed.move_menu_selection(MenuMove::Next));
+        reg.register("menu-previous", |ed| ed.move_menu_selection(MenuMove::Previous));
+        reg.register("menu-down", |ed| ed.move_menu_selection(MenuMove::Down));
+        reg.register("menu-up", |ed| ed.move_menu_selection(MenuMove::Up));
+        reg.register("menu-next-page", |ed| ed.move_menu_selection(MenuMove::NextPage));
+        reg.register("menu-previous-page", |ed| ed.move_menu_selection(MenuMove::PreviousPage));
+        reg.register("menu-accept", |ed| ed.accept_menu_completion());
+        reg.register("menu-cancel", |ed| ed.cancel_menu_completion());
+        reg.register("undo", |ed| ed.undo().map(|_| ()));
+        reg.register("redo", |ed| ed.redo().map(|_| ()));
+        reg.register("revert-line", |ed| ed.revert().map(|_| ()));
+
+        reg
+    }
+
+    /// Creates a registry without any commands.
+    pub fn empty() -> Self {
+        CommandRegistry { commands: HashMap::new() }
+    }
+
+    /// Adds a command called `name`, replacing any existing command with that name.
+    pub fn register<S, F>(&mut self, name: S, command: F)
+    where
+        S: Into<String>,
+        F: for<'a, 'w> Fn(&mut Editor<'a, Write + 'w>) -> io::Result<()> + 'static,
+    {
+        self.commands.insert(name.into(), Rc::new(command));
+    }
+
+    /// Removes the command called `name`. Returns true if there was such a command.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.commands.remove(name).is_some()
+    }
+
+    /// Returns the command called `name`.
+    pub fn get(&self, name: &str) -> Option<Rc<Command>> {
+        self.commands.get(name).cloned()
+    }
+
+    /// Returns true if there is a command called `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.commands.contains_key(name)
+    }
+
+    /// Iterates over the names of all registered commands, in no particular order.
+    pub fn names<'a>(&'a self) -> Box<Iterator<Item = &'a str> + 'a> {
+        Box::new(self.commands.keys().map(|s| &s[..]))
+    }
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        CommandRegistry::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Context;
+
+    #[test]
+    fn run_builtin_command() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        ed.insert_str_after_cursor("hello world").unwrap();
+
+        assert_eq!(ed.run_command("backward-kill-word").unwrap(), true);
+        assert_eq!(ed.run_command("no-such-command").unwrap(), false);
+        assert_eq!(String::from(ed), "hello ");
+    }
+
+    #[test]
+    fn run_user_command() {
+        let mut context = Context::new();
+        context.commands.register("shout", |ed| {
+            let s = String::from(ed.current_buffer().clone()).to_uppercase();
+            ed.move_cursor_to_end_of_line()?;
+            ed.delete_all_before_cursor()?;
+            ed.insert_str_after_cursor(&s)
+        });
+        context.commands.register("shout-twice", |ed| {
+            ed.run_command("shout")?;
+            ed.insert_str_after_cursor("!")
+        });
+
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        ed.insert_str_after_cursor("hi").unwrap();
+        ed.move_cursor_left(1).unwrap();
+
+        assert_eq!(ed.run_command("shout-twice").unwrap(), true);
+        assert_eq!(ed.cursor(), 3);
+        ed.undo().unwrap();
+        assert_eq!(String::from(ed), "HI");
+    }
+
+    #[test]
+    fn history_search() {
+        let mut context = Context::new();
+        context.history.push("git commit".into()).unwrap();
+        context.history.push("ls".into()).unwrap();
+        context.history.push("git push".into()).unwrap();
+
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        ed.insert_str_after_cursor("git").unwrap();
+
+        ed.run_command("history-search-backward").unwrap();
+        assert_eq!(ed.current_history_location(), Some(2));
+        assert_eq!(ed.cursor(), 3);
+        ed.run_command("history-search-backward").unwrap();
+        assert_eq!(ed.current_history_location(), Some(0));
+        ed.run_command("history-search-backward").unwrap();
+        assert_eq!(ed.current_history_location(), Some(0));
+        assert_eq!(ed.cursor(), 3);
+
+        ed.run_command("history-search-forward").unwrap();
+        assert_eq!(ed.current_history_location(), Some(2));
+        ed.run_command("history-search-forward").unwrap();
+        assert_eq!(ed.current_history_location(), Some(2));
+        assert_eq!(String::from(ed), "git push");
+    }
+}
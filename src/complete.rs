@@ -1,125 +1,1027 @@
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
+use Buffer;
+use CursorPosition;
+use MatchStrategy;
+
+/// Completes the word being edited.
+///
+/// Implementors override `completions()` or `rich_completions()`. Each of them is implemented
+/// with the other by default, so at least one must be overridden, even by completers which
+/// override `complete_line()`.
 pub trait Completer {
-    fn completions(&self, start: &str) -> Vec<String>;
+    /// Returns the completions of `start`, the part of the word before the cursor. By default,
+    /// these are the text of the `rich_completions()`.
+    fn completions(&self, start: &str) -> Vec<String> {
+        self.rich_completions(start)
+            .into_iter()
+            .map(|c| c.text)
+            .collect()
+    }
+
+    /// Returns the completions of `start` with their descriptions, display text and suffixes.
+    /// By default, these are the plain `completions()`.
+    fn rich_completions(&self, start: &str) -> Vec<Completion> {
+        self.completions(start)
+            .into_iter()
+            .map(Completion::from)
+            .collect()
+    }
+
+    /// Returns the completions for the line being edited, and the range of the buffer they
+    /// replace. Implement this to complete based on the whole line, for example to complete
+    /// arguments differently from commands. By default, this completes the word before the
+    /// cursor with `rich_completions()`.
+    fn complete_line(&self, line: &CompletionContext) -> LineCompletions {
+        LineCompletions {
+            start: line.word_start(),
+            end: line.cursor,
+            completions: self.rich_completions(&line.word()),
+            hint: None,
+        }
+    }
+}
+
+/// The line being completed, as passed to `Completer::complete_line()`. Settings which aren't
+/// part of the line, like `ignore_case()`, are set with builder methods, so that more can be
+/// added without breaking code which creates a `CompletionContext`.
+#[derive(Debug, Clone, Copy)]
+pub struct CompletionContext<'a> {
+    /// The whole buffer being edited.
+    pub buffer: &'a Buffer,
+    /// The position of the cursor in the buffer, in characters.
+    pub cursor: usize,
+    /// The start and end of each word in the buffer, as split by `Context::word_divider_fn`.
+    pub words: &'a [(usize, usize)],
+    /// The position of the cursor relative to `words`.
+    pub position: CursorPosition,
+    ignore_case: bool,
+}
+
+impl<'a> CompletionContext<'a> {
+    pub fn new(
+        buffer: &'a Buffer,
+        cursor: usize,
+        words: &'a [(usize, usize)],
+        position: CursorPosition,
+    ) -> Self {
+        CompletionContext {
+            buffer: buffer,
+            cursor: cursor,
+            words: words,
+            position: position,
+            ignore_case: false,
+        }
+    }
+
+    /// Ignores case when matching by prefix, as `CompletionOptions::ignore_case` does.
+    pub fn with_ignore_case(mut self, ignore_case: bool) -> Self {
+        self.ignore_case = ignore_case;
+        self
+    }
+
+    /// Whether to ignore case when matching by prefix. False unless set by `with_ignore_case()`.
+    pub fn ignore_case(&self) -> bool {
+        self.ignore_case
+    }
+
+    /// The index of the word being completed. If the cursor isn't touching the end of a word, a
+    /// new word is being completed, and this is the index it will have.
+    pub fn word_index(&self) -> usize {
+        match self.position {
+            CursorPosition::InWord(i) |
+            CursorPosition::OnWordRightEdge(i) |
+            CursorPosition::OnWordLeftEdge(i) |
+            CursorPosition::InSpace(_, Some(i)) => i,
+            CursorPosition::InSpace(Some(i), None) => i + 1,
+            CursorPosition::InSpace(None, None) => 0,
+        }
+    }
+
+    /// The start of the word being completed, or the cursor if a new word is being completed.
+    pub fn word_start(&self) -> usize {
+        match self.position {
+            CursorPosition::InWord(i) | CursorPosition::OnWordRightEdge(i) => self.words[i].0,
+            _ => self.cursor,
+        }
+    }
+
+    /// The part of the word being completed before the cursor.
+    pub fn word(&self) -> String {
+        self.buffer.range(self.word_start(), self.cursor)
+    }
+
+    /// The strategy to match with instead of `strategy`. This is
+    /// `MatchStrategy::CaseInsensitivePrefix` if `strategy` matches by prefix and
+    /// `ignore_case()` is true, and otherwise `strategy` itself.
+    pub fn match_strategy(&self, strategy: MatchStrategy) -> MatchStrategy {
+        match strategy {
+            MatchStrategy::Prefix if self.ignore_case => MatchStrategy::CaseInsensitivePrefix,
+            strategy => strategy,
+        }
+    }
+
+    /// The text of the word with index `i`, if there is one.
+    pub fn nth_word(&self, i: usize) -> Option<String> {
+        self.words
+            .get(i)
+            .map(|&(start, end)| self.buffer.range(start, end))
+    }
+}
+
+/// Completions for a range of the buffer, as returned by `Completer::complete_line()`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LineCompletions {
+    /// The start of the range a completion replaces, in characters.
+    pub start: usize,
+    /// The end of the range a completion replaces, in characters. This is usually the cursor.
+    pub end: usize,
+    pub completions: Vec<Completion>,
+    /// Shown instead when there are no completions, to describe what can be typed.
+    pub hint: Option<String>,
+}
+
+/// A completion, as returned by `Completer::rich_completions()`.
+///
+/// ```
+/// use liner::Completion;
+///
+/// let completion = Completion::new("--verbose")
+///     .with_description("enable verbose output")
+///     .with_suffix(" ");
+/// assert_eq!(completion.display_text(), "--verbose");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Completion {
+    /// The text which replaces the word being completed.
+    pub text: String,
+    /// Shown in the list of completions instead of `text`.
+    pub display: Option<String>,
+    /// Shown next to the completion in the list of completions.
+    pub description: Option<String>,
+    /// Inserted after `text` once the completion is chosen, for example a space after a command
+    /// or a slash after a directory.
+    pub suffix: String,
+    /// How well the completion matches, as given by `MatchStrategy::score()`. Completions with
+    /// higher scores are listed first, and those with the same score are sorted by their text.
+    pub score: i64,
+}
+
+impl Completion {
+    pub fn new<S: Into<String>>(text: S) -> Self {
+        Completion {
+            text: text.into(),
+            ..Completion::default()
+        }
+    }
+
+    pub fn with_display<S: Into<String>>(mut self, display: S) -> Self {
+        self.display = Some(display.into());
+        self
+    }
+
+    pub fn with_description<S: Into<String>>(mut self, description: S) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn with_suffix<S: Into<String>>(mut self, suffix: S) -> Self {
+        self.suffix = suffix.into();
+        self
+    }
+
+    pub fn with_score(mut self, score: i64) -> Self {
+        self.score = score;
+        self
+    }
+
+    /// The text shown in the list of completions.
+    pub fn display_text(&self) -> &str {
+        self.display.as_ref().unwrap_or(&self.text)
+    }
+}
+
+impl From<String> for Completion {
+    fn from(text: String) -> Self {
+        Completion::new(text)
+    }
+}
+
+impl<'a> From<&'a str> for Completion {
+    fn from(text: &'a str) -> Self {
+        Completion::new(text)
+    }
+}
+
+/// Options which change how completions are offered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CompletionOptions {
+    /// If true, the completions are listed on the first tab when they can't be narrowed down any
+    /// further, rather than on the second.
+    pub show_all_if_ambiguous: bool,
+    /// If true, tab inserts a tab character instead of completing.
+    pub disable_completion: bool,
+    /// If true, completions are shown in a menu to select from, instead of being listed.
+    pub menu_select: bool,
+    /// If true, `Context::async_completer` runs on another thread, so that a slow completer
+    /// doesn't freeze the prompt. A spinner is shown while it runs, and pressing a key cancels
+    /// the completion.
+    pub asynchronous: bool,
+    /// How long to wait for an asynchronous completion before cancelling it. None waits until it
+    /// finishes.
+    pub timeout: Option<Duration>,
+    /// If true, completers which match by prefix ignore case, as with
+    /// `MatchStrategy::CaseInsensitivePrefix`. Completers get this from
+    /// `CompletionContext::match_strategy()`.
+    pub ignore_case: bool,
 }
 
 pub struct BasicCompleter {
     prefixes: Vec<String>,
+    strategy: MatchStrategy,
 }
 
 impl BasicCompleter {
     pub fn new<T: Into<String>>(prefixes: Vec<T>) -> BasicCompleter {
-        BasicCompleter { prefixes: prefixes.into_iter().map(|s| s.into()).collect() }
+        BasicCompleter {
+            prefixes: prefixes.into_iter().map(|s| s.into()).collect(),
+            strategy: MatchStrategy::Prefix,
+        }
+    }
+
+    /// Sets how the words are matched. The default is `MatchStrategy::Prefix`.
+    pub fn with_match_strategy(mut self, strategy: MatchStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    fn matches(&self, start: &str, strategy: MatchStrategy) -> Vec<Completion> {
+        let completions = self.prefixes.iter().map(|s| Completion::new(&s[..])).collect();
+        strategy.filter(start, completions)
     }
 }
 
 impl Completer for BasicCompleter {
-    fn completions(&self, start: &str) -> Vec<String> {
-        self.prefixes
-            .iter()
-            .filter(|s| s.starts_with(start))
-            .cloned()
-            .collect()
+    fn rich_completions(&self, start: &str) -> Vec<Completion> {
+        self.matches(start, self.strategy)
+    }
+
+    fn complete_line(&self, line: &CompletionContext) -> LineCompletions {
+        LineCompletions {
+            start: line.word_start(),
+            end: line.cursor,
+            completions: self.matches(&line.word(), line.match_strategy(self.strategy)),
+            hint: None,
+        }
     }
 }
 
+/// Which hidden files, those with names starting with a `.`, `FilenameCompleter` offers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HiddenFiles {
+    Show,
+    Hide,
+    /// Hidden files are offered once the name being completed starts with a `.`.
+    WhenTyped,
+}
+
+/// Completes paths to files and directories. The word being completed is read like a shell
+/// word: it can be quoted with `'`, `"` or backslashes, and `~` and environment variables such as
+/// `$HOME` are expanded. Completions are quoted the same way as the word.
 pub struct FilenameCompleter {
     working_dir: Option<PathBuf>,
+    hidden_files: HiddenFiles,
+    dirs_only: bool,
+    extensions: Vec<String>,
+    strategy: MatchStrategy,
 }
 
 impl FilenameCompleter {
+    /// Relative paths are completed from `working_dir`, or the current directory if it is None.
     pub fn new<T: Into<PathBuf>>(working_dir: Option<T>) -> Self {
-        FilenameCompleter { working_dir: working_dir.map(|p| p.into()) }
+        FilenameCompleter {
+            working_dir: working_dir.map(|p| p.into()),
+            hidden_files: HiddenFiles::WhenTyped,
+            dirs_only: false,
+            extensions: Vec::new(),
+            strategy: MatchStrategy::Prefix,
+        }
+    }
+
+    /// Sets which hidden files are offered. The default is `HiddenFiles::WhenTyped`.
+    pub fn with_hidden_files(mut self, hidden_files: HiddenFiles) -> Self {
+        self.hidden_files = hidden_files;
+        self
+    }
+
+    /// If true, only directories are offered.
+    pub fn with_dirs_only(mut self, dirs_only: bool) -> Self {
+        self.dirs_only = dirs_only;
+        self
+    }
+
+    /// Only offers files with one of `extensions`, such as `"rs"`, and directories. Extensions
+    /// are compared ignoring ASCII case.
+    pub fn with_extensions<I, S>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.extensions = extensions
+            .into_iter()
+            .map(|ext| {
+                let ext = ext.into();
+                if ext.starts_with('.') { ext[1..].to_owned() } else { ext }
+            })
+            .collect();
+        self
+    }
+
+    /// Sets how file names are matched. The default is `MatchStrategy::Prefix`.
+    pub fn with_match_strategy(mut self, strategy: MatchStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// The path of the directory `dir`, as expanded from the word being completed.
+    fn resolve(&self, dir: &str) -> PathBuf {
+        let dir = Path::new(if dir.is_empty() { "." } else { dir });
+        match self.working_dir {
+            Some(ref wd) => wd.join(dir),
+            None => dir.to_owned(),
+        }
+    }
+
+    fn has_extension(&self, file_name: &str) -> bool {
+        if self.extensions.is_empty() {
+            return true;
+        }
+
+        match Path::new(file_name).extension() {
+            Some(ext) => {
+                let ext = ext.to_string_lossy();
+                self.extensions.iter().any(|e| e.eq_ignore_ascii_case(&ext))
+            }
+            None => false,
+        }
     }
 }
 
 impl Completer for FilenameCompleter {
-    fn completions(&self, mut start: &str) -> Vec<String> {
-        // XXX: this function is really bad, TODO rewrite
-
-        let start_owned;
-        if start.starts_with("\"") || start.starts_with("'") {
-            start = &start[1..];
-            if start.len() >= 1 {
-                start = &start[..start.len() - 1];
-            }
-            start_owned = start.into();
-        } else {
-            start_owned = start.replace("\\ ", " ");
+    fn rich_completions(&self, start: &str) -> Vec<Completion> {
+        self.matches(start, self.strategy)
+    }
+
+    /// Completes the shell word before the cursor, which may be longer than the word from
+    /// `Context::word_divider_fn`, since quoted spaces don't end it.
+    fn complete_line(&self, line: &CompletionContext) -> LineCompletions {
+        let start = ShellWord::last_word_start(&line.buffer.range(0, line.cursor));
+        let word = line.buffer.range(start, line.cursor);
+        LineCompletions {
+            start: start,
+            end: line.cursor,
+            completions: self.matches(&word, line.match_strategy(self.strategy)),
+            hint: None,
         }
+    }
+}
 
-        let full_path;
-        let start_path = PathBuf::from(&start_owned[..]);
+impl FilenameCompleter {
+    fn matches(&self, start: &str, strategy: MatchStrategy) -> Vec<Completion> {
+        let word = ShellWord::parse(start);
 
-        if let Some(ref wd) = self.working_dir {
-            let mut fp = PathBuf::from(wd);
-            fp.push(start_owned.clone());
-            full_path = fp;
-        } else {
-            full_path = PathBuf::from(start_owned.clone());
-        }
-
-        let p;
-        let start_name;
-        let completing_dir;
-        match full_path.parent() {
-            // XXX non-unix separaor
-            Some(parent) if start != "" && !start_owned.ends_with("/") &&
-                            !full_path.ends_with("..") => {
-                p = PathBuf::from(parent);
-                start_name = full_path
-                    .file_name()
-                    .unwrap()
-                    .to_string_lossy()
-                    .into_owned();
-                completing_dir = false;
+        // The word as typed up to the file name, the directory it names, and the quote open
+        // before the file name.
+        let (prefix, dir, quote) = match word.last_slash {
+            Some((typed, expanded, quote)) if !word.expanded[expanded..].contains('/') => {
+                (start[..typed].to_owned(), &word.expanded[..expanded], quote)
             }
+            None if !word.expanded.contains('/') => (String::new(), "", Quote::None),
             _ => {
-                p = full_path.clone();
-                start_name = "".into();
-                completing_dir = start == "" || start.ends_with("/") || full_path.ends_with("..");
+                // The slash came from expanding `~` or a variable.
+                if !start.ends_with('/') && self.resolve(&word.expanded).is_dir() {
+                    return vec![Completion::new(format!("{}/", start))];
+                }
+                let i = word.expanded.rfind('/').unwrap() + 1;
+                let dir = &word.expanded[..i];
+                let prefix = format!("{}{}", word.quote.as_str(), word.quote.escape(dir));
+                (prefix, dir, word.quote)
             }
-        }
-
+        };
+        let name = &word.expanded[dir.len()..];
 
-        let read_dir = match p.read_dir() {
+        let read_dir = match self.resolve(dir).read_dir() {
             Ok(x) => x,
             Err(_) => return vec![],
         };
 
-        let mut matches = vec![];
-        for dir in read_dir {
-            let dir = match dir {
-                Ok(x) => x,
-                Err(_) => continue,
+        let show_hidden = match self.hidden_files {
+            HiddenFiles::Show => true,
+            HiddenFiles::Hide => false,
+            HiddenFiles::WhenTyped => name.starts_with('.'),
+        };
+        // the quotes to close and open before the file name, if they change in the file name
+        let requote = if quote == word.quote {
+            String::new()
+        } else {
+            format!("{}{}", quote.as_str(), word.quote.as_str())
+        };
+
+        let mut matches = Vec::new();
+        for entry in read_dir.filter_map(|entry| entry.ok()) {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            if file_name.starts_with('.') && !show_hidden {
+                continue;
+            }
+            let score = match strategy.score(name, &file_name) {
+                Some(score) => score,
+                None => continue,
             };
-            let file_name = dir.file_name();
-            let file_name = file_name.to_string_lossy();
-
-            if start_name == "" || file_name.starts_with(&*start_name) {
-                let mut a = start_path.clone();
-                if !a.is_absolute() {
-                    a = PathBuf::new();
-                } else if !completing_dir && !a.pop() {
-                    return vec![];
-                }
-                a.push(dir.file_name());
-                let mut s = a.to_string_lossy().into_owned();
-                if dir.path().is_dir() {
-                    s = s + "/";
+
+            let is_dir = entry.path().is_dir();
+            if !is_dir && (self.dirs_only || !self.has_extension(&file_name)) {
+                continue;
+            }
+
+            let text = format!("{}{}{}", prefix, requote, word.quote.escape(&file_name));
+            let completion = if is_dir {
+                Completion::new(text + "/").with_display(file_name + "/")
+            } else {
+                Completion::new(text)
+                    .with_display(file_name)
+                    .with_suffix(format!("{} ", word.quote.as_str()))
+            };
+            matches.push(completion.with_score(score));
+        }
+
+        matches.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.text.cmp(&b.text)));
+        matches
+    }
+}
+
+/// Completes the names of commands: the executables in the directories on `$PATH`, and builtins
+/// and aliases given by the application. Words with a `/` in them are paths, which aren't
+/// completed.
+///
+/// The executables are found when commands are first completed, and found again when `$PATH`
+/// changes, after `refresh()`, or when they are older than the refresh interval.
+pub struct CommandCompleter {
+    path: Option<OsString>,
+    builtins: Vec<String>,
+    aliases: Vec<(String, String)>,
+    refresh_interval: Option<Duration>,
+    strategy: MatchStrategy,
+    executables: Mutex<Option<Executables>>,
+}
+
+/// The executables found on a path, and when they were found.
+struct Executables {
+    path: OsString,
+    found: Instant,
+    names: Vec<String>,
+}
+
+impl CommandCompleter {
+    pub fn new() -> Self {
+        CommandCompleter {
+            path: None,
+            builtins: Vec::new(),
+            aliases: Vec::new(),
+            refresh_interval: None,
+            strategy: MatchStrategy::Prefix,
+            executables: Mutex::new(None),
+        }
+    }
+
+    /// Finds executables in the directories of `path`, which is separated like `$PATH`, instead
+    /// of in `$PATH`.
+    pub fn with_path<S: Into<OsString>>(mut self, path: S) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Adds commands built into the application, such as `cd`.
+    pub fn with_builtins<I, S>(mut self, builtins: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.builtins.extend(builtins.into_iter().map(|s| s.into()));
+        self
+    }
+
+    /// Adds aliases, as pairs of the alias and the command it stands for. The command is shown
+    /// as the alias's description.
+    pub fn with_aliases<I, A, C>(mut self, aliases: I) -> Self
+    where
+        I: IntoIterator<Item = (A, C)>,
+        A: Into<String>,
+        C: Into<String>,
+    {
+        self.aliases.extend(aliases.into_iter().map(|(a, c)| (a.into(), c.into())));
+        self
+    }
+
+    /// Finds the executables again when they were found longer than `interval` ago. By
+    /// default, they are kept until `$PATH` changes or `refresh()` is called.
+    pub fn with_refresh_interval(mut self, interval: Duration) -> Self {
+        self.refresh_interval = Some(interval);
+        self
+    }
+
+    /// Sets how command names are matched. The default is `MatchStrategy::Prefix`.
+    pub fn with_match_strategy(mut self, strategy: MatchStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Finds the executables again the next time commands are completed, for example after
+    /// a program was installed.
+    pub fn refresh(&self) {
+        *self.executables.lock().unwrap() = None;
+    }
+
+    /// The names of the executables on the path, sorted.
+    pub fn executables(&self) -> Vec<String> {
+        let path = self.path
+            .clone()
+            .or_else(|| env::var_os("PATH"))
+            .unwrap_or_default();
+
+        let mut executables = self.executables.lock().unwrap();
+        let stale = match *executables {
+            Some(ref e) => {
+                e.path != path || self.refresh_interval.map_or(false, |i| e.found.elapsed() >= i)
+            }
+            None => true,
+        };
+        if stale {
+            *executables = Some(Executables {
+                names: find_executables(&path),
+                path: path,
+                found: Instant::now(),
+            });
+        }
+
+        executables.as_ref().unwrap().names.clone()
+    }
+}
+
+impl Default for CommandCompleter {
+    fn default() -> Self {
+        CommandCompleter::new()
+    }
+}
+
+impl Completer for CommandCompleter {
+    fn rich_completions(&self, start: &str) -> Vec<Completion> {
+        self.matches(start, self.strategy)
+    }
+
+    fn complete_line(&self, line: &CompletionContext) -> LineCompletions {
+        LineCompletions {
+            start: line.word_start(),
+            end: line.cursor,
+            completions: self.matches(&line.word(), line.match_strategy(self.strategy)),
+            hint: None,
+        }
+    }
+}
+
+impl CommandCompleter {
+    fn matches(&self, start: &str, strategy: MatchStrategy) -> Vec<Completion> {
+        if start.contains('/') {
+            return Vec::new();
+        }
+
+        let aliases = self.aliases
+            .iter()
+            .map(|alias| Completion::new(&alias.0[..]).with_description(&alias.1[..]));
+        let builtins = self.builtins.iter().map(|b| Completion::new(&b[..]));
+        let executables = self.executables().into_iter().map(Completion::new);
+
+        // aliases hide builtins, which hide executables
+        let mut seen = HashSet::new();
+        let completions = aliases
+            .chain(builtins)
+            .chain(executables)
+            .filter(|c| seen.insert(c.text.clone()))
+            .map(|c| c.with_suffix(" "))
+            .collect();
+        strategy.filter(start, completions)
+    }
+}
+
+/// The names of the executable files in the directories of `path`, sorted.
+fn find_executables(path: &OsStr) -> Vec<String> {
+    let mut names = Vec::new();
+    for dir in env::split_paths(path) {
+        let read_dir = match dir.read_dir() {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+
+        for entry in read_dir.filter_map(|entry| entry.ok()) {
+            // follows symlinks
+            match fs::metadata(entry.path()) {
+                Ok(ref metadata) if is_executable(metadata) => {
+                    names.push(entry.file_name().to_string_lossy().into_owned())
                 }
+                _ => {}
+            }
+        }
+    }
+
+    names.sort();
+    names.dedup();
+    names
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    metadata.is_file()
+}
+
+/// The quote open at a point in a shell word.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quote {
+    None,
+    Single,
+    Double,
+}
+
+impl Quote {
+    /// The character which opens and closes the quote.
+    fn as_str(self) -> &'static str {
+        match self {
+            Quote::None => "",
+            Quote::Single => "'",
+            Quote::Double => "\"",
+        }
+    }
+
+    /// Escapes `s` so that it is read literally inside the quote.
+    fn escape(self, s: &str) -> String {
+        let special: &[char] = match self {
+            Quote::None => &[
+                ' ', '\t', '\n', '\\', '\'', '"', '$', '`', '&', ';', '|', '<', '>', '(', ')',
+                '*', '?', '[', ']', '{', '}', '!', '#', '~',
+            ],
+            Quote::Double => &['\\', '"', '$', '`'],
+            Quote::Single => return s.replace('\'', "'\\''"),
+        };
+
+        let mut res = String::with_capacity(s.len());
+        for c in s.chars() {
+            if special.contains(&c) {
+                res.push('\\');
+            }
+            res.push(c);
+        }
+        res
+    }
+}
+
+/// A word typed in the shell, with quotes removed and `~` and variables expanded.
+struct ShellWord {
+    expanded: String,
+    /// The quote still open at the end of the word.
+    quote: Quote,
+    /// For the last `/` typed in the word, the length of the word as typed and as expanded up
+    /// to and including it, and the quote open there.
+    last_slash: Option<(usize, usize, Quote)>,
+}
+
+impl ShellWord {
+    fn parse(word: &str) -> Self {
+        let mut expanded = String::new();
+        let mut quote = Quote::None;
+        let mut last_slash = None;
+        let mut chars = word.char_indices().peekable();
+
+        if word == "~" || word.starts_with("~/") {
+            if let Some(home) = env::var_os("HOME") {
+                expanded.push_str(&home.to_string_lossy());
+                chars.next();
+            }
+        }
+
+        while let Some((i, c)) = chars.next() {
+            match (quote, c) {
+                (Quote::None, '\'') => quote = Quote::Single,
+                (Quote::None, '"') => quote = Quote::Double,
+                (Quote::Single, '\'') | (Quote::Double, '"') => quote = Quote::None,
+                (Quote::None, '\\') => if let Some((_, c)) = chars.next() {
+                    expanded.push(c);
+                },
+                (Quote::Double, '\\') => match chars.peek() {
+                    Some(&(_, c)) if "\\\"$`".contains(c) => {
+                        chars.next();
+                        expanded.push(c);
+                    }
+                    _ => expanded.push('\\'),
+                },
+                (Quote::None, '$') | (Quote::Double, '$') => {
+                    let braced = chars.peek().map_or(false, |&(_, c)| c == '{');
+                    if braced {
+                        chars.next();
+                    }
 
-                let mut b = PathBuf::from(start_owned.clone());
-                if !completing_dir {
-                    b.pop();
+                    let mut name = String::new();
+                    while let Some(&(_, c)) = chars.peek() {
+                        if braced && c == '}' {
+                            chars.next();
+                            break;
+                        } else if !braced && !c.is_alphanumeric() && c != '_' {
+                            break;
+                        }
+                        name.push(c);
+                        chars.next();
+                    }
+
+                    if name.is_empty() && !braced {
+                        expanded.push('$');
+                    } else if let Some(value) = env::var_os(name) {
+                        expanded.push_str(&value.to_string_lossy());
+                    }
+                }
+                (_, c) => {
+                    expanded.push(c);
+                    if c == '/' {
+                        last_slash = Some((i + 1, expanded.len(), quote));
+                    }
                 }
-                b.push(s);
+            }
+        }
+
+        ShellWord {
+            expanded: expanded,
+            quote: quote,
+            last_slash: last_slash,
+        }
+    }
+
+    /// Where the last word of `text` starts, in characters. Words are separated by spaces which
+    /// aren't quoted or escaped with a backslash.
+    fn last_word_start(text: &str) -> usize {
+        let mut start = 0;
+        let mut quote = Quote::None;
+        let mut escaped = false;
+        for (i, c) in text.chars().enumerate() {
+            if escaped {
+                escaped = false;
+                continue;
+            }
+            match (quote, c) {
+                (Quote::None, '\'') => quote = Quote::Single,
+                (Quote::None, '"') => quote = Quote::Double,
+                (Quote::Single, '\'') | (Quote::Double, '"') => quote = Quote::None,
+                (Quote::None, '\\') | (Quote::Double, '\\') => escaped = true,
+                (Quote::None, ' ') => start = i + 1,
+                _ => {}
+            }
+        }
+        start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                matches.push(b.to_string_lossy().to_owned().replace(" ", "\\ "));
+    /// Creates an empty directory for a test with these files and directories in it.
+    fn test_dir(name: &str, files: &[&str]) -> PathBuf {
+        let dir = env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        for file in files {
+            if file.ends_with('/') {
+                fs::create_dir_all(dir.join(file)).unwrap();
+            } else {
+                fs::create_dir_all(dir.join(file).parent().unwrap()).unwrap();
+                fs::File::create(dir.join(file)).unwrap();
             }
         }
+        dir
+    }
 
-        matches
+    const FILES: &[&str] = &[
+        "My Documents/",
+        "My File.txt",
+        "it's",
+        "notes.md",
+        "src/main.rs",
+        ".hidden",
+    ];
+
+    #[test]
+    fn filenames() {
+        let dir = test_dir("liner_test_filenames", FILES);
+        let completer = FilenameCompleter::new(Some(&dir));
+
+        assert_eq!(
+            completer.completions(""),
+            vec!["My\\ Documents/", "My\\ File.txt", "it\\'s", "notes.md", "src/"]
+        );
+        assert_eq!(completer.completions("src/"), vec!["src/main.rs"]);
+        assert_eq!(completer.completions("src/x"), Vec::<String>::new());
+        assert_eq!(completer.completions("nothing/"), Vec::<String>::new());
+
+        let completions = completer.rich_completions("My");
+        assert_eq!(completions[0].display_text(), "My Documents/");
+        assert_eq!(completions[0].suffix, "");
+        assert_eq!(completions[1].display_text(), "My File.txt");
+        assert_eq!(completions[1].suffix, " ");
+
+        let completions = FilenameCompleter::new(None::<PathBuf>).rich_completions(
+            &format!("{}/n", dir.to_string_lossy()),
+        );
+        assert_eq!(completions[0].text, format!("{}/notes.md", dir.to_string_lossy()));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn quoted_filenames() {
+        let dir = test_dir("liner_test_quoted_filenames", FILES);
+        let completer = FilenameCompleter::new(Some(&dir));
+
+        assert_eq!(completer.completions("My\\ F"), vec!["My\\ File.txt"]);
+        assert_eq!(completer.completions("\"My F"), vec!["\"My File.txt"]);
+        assert_eq!(completer.completions("'My D"), vec!["'My Documents/"]);
+        assert_eq!(completer.completions("it"), vec!["it\\'s"]);
+        assert_eq!(completer.completions("'it"), vec!["'it'\\''s"]);
+        assert_eq!(completer.completions("My' 'F"), vec!["My\\ File.txt"]);
+        assert_eq!(completer.completions("'My Documents'/"), Vec::<String>::new());
+        assert_eq!(completer.completions("'src'/m"), vec!["'src'/main.rs"]);
+        assert_eq!(completer.completions("'src/'m"), vec!["'src/'main.rs"]);
+        assert_eq!(completer.completions("'src/m"), vec!["'src/main.rs"]);
+        assert_eq!(completer.completions("\"src/\"m"), vec!["\"src/\"main.rs"]);
+        assert_eq!(completer.completions("src/'m"), vec!["src/'main.rs"]);
+
+        assert_eq!(completer.rich_completions("\"My F")[0].suffix, "\" ");
+        assert_eq!(completer.rich_completions("'it")[0].suffix, "' ");
+
+        // quoted spaces are part of the word being completed
+        for &(text, start) in [("cat 'My D", 4), ("cat \"it's\" My\\ D", 11)].iter() {
+            let buf = Buffer::from(text);
+            let words = ::get_buffer_words(&buf);
+            let position = CursorPosition::get(buf.num_chars(), &words);
+            let line = CompletionContext::new(&buf, buf.num_chars(), &words, position);
+            let res = completer.complete_line(&line);
+            assert_eq!(res.start, start);
+            assert_eq!(res.completions.len(), 1);
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn expanded_filenames() {
+        let dir = test_dir("liner_test_expanded_filenames", FILES);
+        env::set_var("LINER_TEST_FILENAMES", &dir);
+        let completer = FilenameCompleter::new(None::<PathBuf>);
+
+        assert_eq!(
+            completer.completions("$LINER_TEST_FILENAMES/n"),
+            vec!["$LINER_TEST_FILENAMES/notes.md"]
+        );
+        assert_eq!(
+            completer.completions("\"${LINER_TEST_FILENAMES}/s"),
+            vec!["\"${LINER_TEST_FILENAMES}/src/"]
+        );
+        assert_eq!(
+            completer.completions("$LINER_TEST_FILENAMES"),
+            vec!["$LINER_TEST_FILENAMES/"]
+        );
+        assert_eq!(
+            completer.completions("$LINER_TEST_FILENAMES/My\\ Documents"),
+            vec!["$LINER_TEST_FILENAMES/My\\ Documents/"]
+        );
+
+        let dir = dir.to_string_lossy().into_owned();
+        assert_eq!(ShellWord::parse("$LINER_TEST_FILENAMES").expanded, dir);
+        assert_eq!(ShellWord::parse("'$LINER_TEST_FILENAMES'").expanded, "$LINER_TEST_FILENAMES");
+        assert_eq!(ShellWord::parse("\\$LINER_TEST_FILENAMES").expanded, "$LINER_TEST_FILENAMES");
+        assert_eq!(ShellWord::parse("$LINER_TEST_NOT_SET/a").expanded, "/a");
+        assert_eq!(ShellWord::parse("a$").expanded, "a$");
+
+        if let Some(home) = env::var_os("HOME") {
+            let home = home.to_string_lossy().into_owned();
+            assert_eq!(ShellWord::parse("~").expanded, home);
+            assert_eq!(ShellWord::parse("~/a").expanded, format!("{}/a", home));
+            assert_eq!(ShellWord::parse("a~").expanded, "a~");
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn filename_filters() {
+        let dir = test_dir("liner_test_filename_filters", FILES);
+
+        let completer = FilenameCompleter::new(Some(&dir));
+        assert_eq!(completer.completions("."), vec![".hidden"]);
+        let completer = completer.with_hidden_files(HiddenFiles::Show);
+        assert_eq!(completer.completions("")[0], ".hidden");
+        let completer = completer.with_hidden_files(HiddenFiles::Hide);
+        assert_eq!(completer.completions("."), Vec::<String>::new());
+
+        let completer = FilenameCompleter::new(Some(&dir)).with_dirs_only(true);
+        assert_eq!(completer.completions(""), vec!["My\\ Documents/", "src/"]);
+
+        let completer = FilenameCompleter::new(Some(&dir)).with_extensions(vec![".MD", "txt"]);
+        assert_eq!(
+            completer.completions(""),
+            vec!["My\\ Documents/", "My\\ File.txt", "notes.md", "src/"]
+        );
+
+        let completer = FilenameCompleter::new(Some(&dir))
+            .with_match_strategy(MatchStrategy::CaseInsensitivePrefix);
+        assert_eq!(completer.completions("my"), vec!["My\\ Documents/", "My\\ File.txt"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    fn make_executable(path: &Path) {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn commands() {
+        let bin = test_dir("liner_test_commands_bin", &["ls", "lsblk", "lsnotes", "lsdir/"]);
+        let sbin = test_dir("liner_test_commands_sbin", &["ls", "lsmod"]);
+        for path in &[bin.join("ls"), bin.join("lsblk"), sbin.join("ls"), sbin.join("lsmod")] {
+            make_executable(path);
+        }
+
+        let path = env::join_paths(&[&bin, &sbin]).unwrap();
+        let completer = CommandCompleter::new()
+            .with_path(path)
+            .with_builtins(vec!["cd", "lsecho"])
+            .with_aliases(vec![("ll", "ls -l"), ("lsmod", "lsmod -v")]);
+
+        assert_eq!(completer.executables(), vec!["ls", "lsblk", "lsmod"]);
+        assert_eq!(
+            completer.completions("l"),
+            vec!["ll", "ls", "lsblk", "lsecho", "lsmod"]
+        );
+        assert_eq!(completer.completions("./l"), Vec::<String>::new());
+
+        let completions = completer.rich_completions("lsm");
+        assert_eq!(completions[0].description, Some("lsmod -v".to_owned()));
+        assert_eq!(completions[0].suffix, " ");
+
+        // new executables are found after a refresh
+        fs::File::create(bin.join("lsof")).unwrap();
+        make_executable(&bin.join("lsof"));
+        assert_eq!(completer.completions("lso"), Vec::<String>::new());
+        completer.refresh();
+        assert_eq!(completer.completions("lso"), vec!["lsof"]);
+
+        let completer = completer.with_refresh_interval(Duration::from_secs(0));
+        fs::remove_file(bin.join("lsof")).unwrap();
+        assert_eq!(completer.completions("lso"), Vec::<String>::new());
+
+        fs::remove_dir_all(bin).unwrap();
+        fs::remove_dir_all(sbin).unwrap();
+    }
+
+    /// Overrides only `completions()`.
+    struct PlainCompleter;
+
+    impl Completer for PlainCompleter {
+        fn completions(&self, start: &str) -> Vec<String> {
+            vec![format!("{}plain", start)]
+        }
+    }
+
+    /// Overrides only `rich_completions()`.
+    struct RichCompleter;
+
+    impl Completer for RichCompleter {
+        fn rich_completions(&self, start: &str) -> Vec<Completion> {
+            vec![Completion::new(format!("{}rich", start)).with_description("rich")]
+        }
+    }
+
+    #[test]
+    fn default_completions() {
+        assert_eq!(PlainCompleter.rich_completions("a"), vec![Completion::new("aplain")]);
+        assert_eq!(RichCompleter.completions("a"), vec!["arich"]);
     }
 }
@@ -1,5 +1,8 @@
-use std::io::{self, stdin, stdout, Stdout, Write};
-use termion::input::TermRead;
+use std::collections::HashMap;
+use std::io::{self, stdin, stdout, Read, Stdout, Write};
+use std::sync::Arc;
+use termion::event::Event as TermEvent;
+use termion::input::TermReadEventsAndRaw;
 use termion::raw::{IntoRawMode, RawTerminal};
 
 use super::*;
@@ -39,6 +42,31 @@ pub fn get_buffer_words(buf: &Buffer) -> Vec<(usize, usize)> {
     res
 }
 
+/// Sent by the terminal before and after pasted text, when bracketed paste is enabled.
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// Enables bracketed paste until it is dropped, so that the terminal is restored however
+/// `Context::read_line()` returns.
+struct BracketedPaste;
+
+impl BracketedPaste {
+    fn enable() -> io::Result<Self> {
+        let mut out = stdout();
+        try!(out.write_all(b"\x1b[?2004h"));
+        try!(out.flush());
+        Ok(BracketedPaste)
+    }
+}
+
+impl Drop for BracketedPaste {
+    fn drop(&mut self) {
+        let mut out = stdout();
+        let _ = out.write_all(b"\x1b[?2004l");
+        let _ = out.flush();
+    }
+}
+
 /// The key bindings to use.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum KeyBindings {
@@ -49,8 +77,30 @@ pub enum KeyBindings {
 pub struct Context {
     pub history: History,
     pub completer: Option<Box<Completer>>,
+    /// A completer which can run on another thread. If set, it is used instead of `completer`,
+    /// and runs in the background when `CompletionOptions::asynchronous` is set.
+    pub async_completer: Option<Arc<Completer + Send + Sync>>,
+    pub completion_options: CompletionOptions,
+    /// Colors the buffer as it is displayed.
+    pub highlighter: Option<Box<Highlighter>>,
+    /// Suggests how the line could continue. None turns autosuggestions off.
+    pub autosuggestion_provider: Option<Box<AutosuggestionProvider>>,
     pub word_divider_fn: Box<Fn(&Buffer) -> Vec<(usize, usize)>>,
     pub key_bindings: KeyBindings,
+    /// Maps keys to the commands they run, for both Emacs and Vi key bindings.
+    pub key_binding_table: KeyBindingTable,
+    /// The named commands which keys can be bound to.
+    pub commands: CommandRegistry,
+    /// Killed text, which can be yanked back. Shared by every call to `read_line()`.
+    pub kill_ring: KillRing,
+    /// The contents of the Vi registers, by name. The unnamed register is `'"'`.
+    pub vi_registers: HashMap<char, String>,
+    /// Whether to enable the terminal's bracketed paste mode while reading a line. Pasted text
+    /// is then inserted literally, instead of being handled as keypresses. True by default.
+    pub bracketed_paste: bool,
+    /// The asynchronous completion which was cancelled before it finished, if it is still
+    /// running. The next completion reuses it or waits for it.
+    pub background_completion: Option<BackgroundCompletion>,
 }
 
 impl Context {
@@ -58,8 +108,18 @@ impl Context {
         Context {
             history: History::new(),
             completer: None,
+            async_completer: None,
+            completion_options: CompletionOptions::default(),
+            highlighter: None,
+            autosuggestion_provider: Some(Box::new(HistoryAutosuggestions)),
             word_divider_fn: Box::new(get_buffer_words),
             key_bindings: KeyBindings::Emacs,
+            key_binding_table: KeyBindingTable::new(),
+            commands: CommandRegistry::new(),
+            kill_ring: KillRing::new(),
+            vi_registers: HashMap::new(),
+            bracketed_paste: true,
+            background_completion: None,
         }
     }
 
@@ -91,10 +151,21 @@ impl Context {
         mut handler: &mut EventHandler<RawTerminal<Stdout>>,
         buffer: B,
     ) -> io::Result<String> {
+        if self.history.share_history {
+            let _ = self.history.load_new_entries();
+        }
+
+        let key_bindings = self.key_bindings;
+        let _paste = if self.bracketed_paste {
+            Some(try!(BracketedPaste::enable()))
+        } else {
+            None
+        };
+
         let res = {
             let stdout = stdout().into_raw_mode().unwrap();
             let ed = try!(Editor::new_with_init_buffer(stdout, prompt, self, buffer));
-            match self.key_bindings {
+            match key_bindings {
                 KeyBindings::Emacs => Self::handle_keys(keymap::Emacs::new(ed), handler),
                 KeyBindings::Vi => Self::handle_keys(keymap::Vi::new(ed), handler),
             }
@@ -105,15 +176,51 @@ impl Context {
     }
 
     fn handle_keys<'a, T, W: Write, M: KeyMap<'a, W, T>>(
-        mut keymap: M,
+        keymap: M,
         mut handler: &mut EventHandler<W>,
     ) -> io::Result<String>
     where
         String: From<M>,
     {
         let stdin = stdin();
-        for c in stdin.keys() {
-            if try!(keymap.handle_key(c.unwrap(), handler)) {
+        Self::handle_input(keymap, stdin.lock(), handler)
+    }
+
+    /// Feeds keypresses from `input` to the keymap until the line is entered. Bracketed pastes
+    /// are inserted as they are.
+    fn handle_input<'a, T, W: Write, M: KeyMap<'a, W, T>, R: Read>(
+        mut keymap: M,
+        input: R,
+        handler: &mut EventHandler<W>,
+    ) -> io::Result<String>
+    where
+        String: From<M>,
+    {
+        let mut events = input.events_and_raw();
+        while let Some(event) = events.next() {
+            let done = match try!(event) {
+                (TermEvent::Key(key), _) => try!(keymap.handle_key(key, handler)),
+                (_, ref raw) if &raw[..] == PASTE_START => {
+                    let mut pasted = Vec::new();
+                    while let Some(event) = events.next() {
+                        match event {
+                            Ok((_, ref raw)) if &raw[..] == PASTE_END => break,
+                            Ok((_, raw)) => pasted.extend(raw),
+                            // invalid UTF-8 in the pasted text is skipped
+                            Err(_) => {}
+                        }
+                    }
+                    try!(keymap.handle_paste(&String::from_utf8_lossy(&pasted), handler));
+                    false
+                }
+                (TermEvent::Unsupported(_), raw) => {
+                    let keys = unsupported_key_sequence(&raw);
+                    try!(keymap.handle_unsupported_keys(&keys, handler))
+                }
+                _ => false,
+            };
+
+            if done {
                 break;
             }
         }
@@ -127,3 +234,56 @@ impl Context {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bracketed_paste() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let map = keymap::Emacs::new(ed);
+
+        // neither the tab nor the newline in the pasted text are handled as keys
+        let input = &b"ab\x1b[200~x\ty\r\nz\x1b[201~c\r"[..];
+        let line = Context::handle_input(map, input, &mut |_| {}).unwrap();
+        assert_eq!(line, "abx\ty\nzc");
+    }
+
+    #[test]
+    fn unsupported_keys() {
+        let mut context = Context::new();
+        context.history.push("git commit -m fix".into()).unwrap();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let map = keymap::Emacs::new(ed);
+
+        // ctrl-right accepts a word of the autosuggestion, alt-right a character
+        let input = &b"git\x1b[1;5C\x1b[1;3C\x1b[1;3C\r"[..];
+        let line = Context::handle_input(map, input, &mut |_| {}).unwrap();
+        assert_eq!(line, "git commit -");
+    }
+
+    #[test]
+    fn rebind_unsupported_keys() {
+        let mut context = Context::new();
+        let ctrl_right = unsupported_key_sequence(b"\x1b[1;5C");
+        context.key_binding_table.bind(BindingMode::Emacs, &ctrl_right, "beginning-of-line");
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let map = keymap::Emacs::new(ed);
+
+        let mut keys = Vec::new();
+        let input = &b"ls[1;5Ccd 
+"[..];
+        let line = Context::handle_input(map, input, &mut |e| {
+            if let EventKind::BeforeKey(key) = e.kind {
+                keys.push(key);
+            }
+        }).unwrap();
+        assert_eq!(line, "cd ls");
+        assert_eq!(keys[2..8], ctrl_right[..]);
+    }
+}
@@ -1,9 +1,20 @@
 use std::cmp;
+use std::mem;
 use std::io::{self, Write};
-use termion::{self, clear, color, cursor};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use libc;
+use termion::{self, clear, color, cursor, style};
 
 use Context;
 use Buffer;
+use Completer;
+use Completion;
+use CompletionContext;
+use LineCompletions;
+use SearchDirection;
 use event::*;
 use util;
 
@@ -54,10 +65,124 @@ impl CursorPosition {
     }
 }
 
+/// How often to check whether a completion running in the background has finished, or a key has
+/// been pressed.
+const COMPLETION_POLL_MS: u64 = 10;
+
+/// How long a completion runs in the background before the spinner is shown, and how long each
+/// frame of the spinner is shown.
+const SPINNER_DELAY_MS: u64 = 100;
+const SPINNER_FRAME_MS: u64 = 100;
+const SPINNER: &[char] = &['|', '/', '-', '\\'];
+
+/// A completion running on another thread, which was cancelled before it finished. It is kept in
+/// `Context::background_completion`, so that the next completion can reuse it or wait for it,
+/// instead of leaving more and more completer threads running.
+pub struct BackgroundCompletion {
+    completer: Arc<Completer + Send + Sync>,
+    buffer: Buffer,
+    cursor: usize,
+    ignore_case: bool,
+    rx: Receiver<LineCompletions>,
+}
+
+impl BackgroundCompletion {
+    fn spawn(
+        completer: Arc<Completer + Send + Sync>,
+        buffer: Buffer,
+        cursor: usize,
+        words: Vec<(usize, usize)>,
+        pos: CursorPosition,
+        ignore_case: bool,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let thread_completer = completer.clone();
+        let thread_buffer = buffer.clone();
+        thread::spawn(move || {
+            let line = CompletionContext::new(&thread_buffer, cursor, &words, pos)
+                .with_ignore_case(ignore_case);
+            // nothing receives the completions if the receiver was dropped
+            let _ = tx.send(thread_completer.complete_line(&line));
+        });
+
+        BackgroundCompletion {
+            completer: completer,
+            buffer: buffer,
+            cursor: cursor,
+            ignore_case: ignore_case,
+            rx: rx,
+        }
+    }
+
+    /// Whether this completes `buffer` at `cursor` with `completer`.
+    fn completes(
+        &self,
+        completer: &Arc<Completer + Send + Sync>,
+        buffer: &Buffer,
+        cursor: usize,
+        ignore_case: bool,
+    ) -> bool {
+        Arc::ptr_eq(&self.completer, completer) && self.buffer.chars().eq(buffer.chars())
+            && self.cursor == cursor && self.ignore_case == ignore_case
+    }
+}
+
+/// The state of an interactive history search.
+struct Search {
+    query: String,
+    direction: SearchDirection,
+
+    // True if nothing in history matches the query.
+    failed: bool,
+
+    // Shown with the query on the line below the buffer for a search which only runs once it is
+    // submitted. None for incremental searches.
+    prompt: Option<String>,
+
+    // Where we were before the search started, so that it can be cancelled.
+    orig_history_loc: Option<usize>,
+    orig_cursor: usize,
+}
+
+/// How to move the selection in the completion menu.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuMove {
+    Next,
+    Previous,
+    Up,
+    Down,
+    NextPage,
+    PreviousPage,
+}
+
+/// The state of the interactive completion menu.
+struct CompletionMenu {
+    completions: Vec<Completion>,
+    selected: usize,
+
+    // Where the word being completed starts, the word as it was typed, and the length of the
+    // selected completion which replaces it in the buffer.
+    word_start: usize,
+    original: Vec<char>,
+    inserted_len: usize,
+
+    // The layout of the menu when it was last displayed, to move up, down and by pages.
+    columns: usize,
+    page_size: usize,
+}
+
+/// Something done by a key which the next key can continue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyAction {
+    /// Text was killed, so another kill is added to it in the kill ring.
+    Kill,
+    /// Text was yanked between the two positions, so yank-pop can replace it.
+    Yank(usize, usize),
+}
+
 /// The core line editor. Displays and provides editing for history and the new buffer.
-pub struct Editor<'a, W: Write> {
+pub struct Editor<'a, W: Write + ?Sized> {
     prompt: String,
-    out: W,
     context: &'a mut Context,
 
     // The location of the cursor. Note that the cursor does not lie on a char, but between chars.
@@ -79,12 +204,41 @@ pub struct Editor<'a, W: Write> {
     // If this is true, on the next tab we print the completion list.
     show_completions_hint: bool,
 
-    // Show autosuggestions based on history
+    // Show autosuggestions from `Context::autosuggestion_provider`
     show_autosuggestions: bool,
 
+    // The autosuggestion shown by the last display.
+    autosuggestion: Option<Buffer>,
+
     // if set, the cursor will not be allow to move one past the end of the line, this is necessary
     // for Vi's normal mode.
     pub no_eol: bool,
+
+    // Some if we're in the middle of a history search.
+    search: Option<Search>,
+
+    // The query of the last finished history search, reused when searching with an empty query.
+    last_search_query: String,
+
+    // What the previous key did, and what the current key has done so far.
+    last_key_action: Option<KeyAction>,
+    key_action: Option<KeyAction>,
+
+    // Where the selection started, and whether it selects whole lines. None if nothing is
+    // selected.
+    selection: Option<(usize, bool)>,
+
+    // Some if completions are being selected from a menu.
+    menu: Option<CompletionMenu>,
+
+    // The frame of the spinner shown while completing in the background, if it is shown.
+    spinner: Option<usize>,
+
+    // Whether a key has been pressed, which cancels completing in the background.
+    key_pressed: fn() -> bool,
+
+    // Last, so that an `Editor<W>` can be used as an `Editor<Write>`.
+    out: W,
 }
 
 macro_rules! cur_buf_mut {
@@ -125,8 +279,17 @@ impl<'a, W: Write> Editor<'a, W> {
             context: context,
             show_completions_hint: false,
             show_autosuggestions: true,
+            autosuggestion: None,
             term_cursor_line: 1,
             no_eol: false,
+            search: None,
+            last_search_query: String::new(),
+            last_key_action: None,
+            key_action: None,
+            selection: None,
+            menu: None,
+            spinner: None,
+            key_pressed: stdin_has_input,
         };
 
         if !ed.new_buf.is_empty() {
@@ -137,6 +300,31 @@ impl<'a, W: Write> Editor<'a, W> {
         Ok(ed)
     }
 
+    /// Runs the command called `name` from `Context::commands`, for example `"kill-line"`.
+    ///
+    /// Returns `Ok(false)` if there is no such command. Commands which end the line, like
+    /// `"accept-line"`, are run by the keymap instead; see `KeyMap::run_command()`.
+    pub fn run_command(&mut self, name: &str) -> io::Result<bool> {
+        let ed: &mut Editor<Write> = self;
+        ed.run_command(name)
+    }
+}
+
+impl<'a, 'w> Editor<'a, Write + 'w> {
+    /// Runs the command called `name` from `Context::commands`, for example `"kill-line"`.
+    ///
+    /// Returns `Ok(false)` if there is no such command.
+    pub fn run_command(&mut self, name: &str) -> io::Result<bool> {
+        let command = match self.context.commands.get(name) {
+            Some(command) => command,
+            None => return Ok(false),
+        };
+        command(self).map(|_| true)
+    }
+}
+
+impl<'a, W: Write + ?Sized> Editor<'a, W> {
+
     /// None if we're on the new buffer, else the index of history
     pub fn current_history_location(&self) -> Option<usize> {
         self.cur_history_loc
@@ -170,6 +358,7 @@ impl<'a, W: Write> Editor<'a, W> {
             Ok(false)
         } else {
             self.cursor = cur_buf!(self).num_chars();
+            self.selection = None;
             self._display(false)?;
             try!(self.out.write(b"\r\n"));
             self.show_completions_hint = false;
@@ -215,16 +404,9 @@ impl<'a, W: Write> Editor<'a, W> {
         Ok(did)
     }
 
-    fn print_completion_list(&mut self, completions: &[String]) -> io::Result<()> {
-        use std::cmp::max;
-
-        let (w, _) = try!(termion::terminal_size());
-
-        // XXX wide character support
-        let max_word_size = completions.iter().fold(1, |m, x| max(m, x.chars().count()));
-        let cols = max(1, (w as usize / (max_word_size)));
-        let col_width = 2 + w as usize / cols;
-        let cols = max(1, w as usize / col_width);
+    fn print_completion_list(&mut self, completions: &[Completion]) -> io::Result<()> {
+        let (w, _) = try!(terminal_size());
+        let (cols, col_width) = completion_columns(completions, w);
 
         let mut i = 0;
         for com in completions {
@@ -235,7 +417,7 @@ impl<'a, W: Write> Editor<'a, W> {
                 unreachable!()
             }
 
-            try!(write!(self.out, "{:<1$}", com, col_width));
+            try!(write!(self.out, "{}", format_completion(com, col_width, w)));
 
             i += 1;
         }
@@ -250,57 +432,53 @@ impl<'a, W: Write> Editor<'a, W> {
     }
 
     pub fn complete(&mut self, handler: &mut EventHandler<W>) -> io::Result<()> {
-        handler(Event::new(self, EventKind::BeforeComplete));
-
-        let (word, completions) = {
-            let word_range = self.get_word_before_cursor(false);
-            let buf = cur_buf_mut!(self);
+        if self.context.completion_options.disable_completion {
+            return self.insert_after_cursor('\t');
+        }
 
-            let word = match word_range {
-                Some((start, end)) => buf.range(start, end),
-                None => "".into(),
-            };
+        handler(Event::new(self, EventKind::BeforeComplete));
 
-            if let Some(ref completer) = self.context.completer {
-                let mut completions = completer.completions(word.as_ref());
-                completions.sort();
-                completions.dedup();
-                (word, completions)
-            } else {
-                return Ok(());
-            }
+        let LineCompletions { start, end, completions, hint } = match try!(self.line_completions())
+        {
+            Some(completions) => completions,
+            None => return Ok(()),
         };
 
         if completions.len() == 0 {
-            // Do nothing.
             self.show_completions_hint = false;
-            Ok(())
+            match hint {
+                Some(hint) => self.print_hint(&hint),
+                None => Ok(()),
+            }
         } else if completions.len() == 1 {
             self.show_completions_hint = false;
-            try!(self.delete_word_before_cursor(false));
-            self.insert_str_after_cursor(completions[0].as_ref())
+            self.insert_completion(start, end, &completions[0])
         } else {
             let common_prefix = util::find_longest_common_prefix(
                 &completions
                     .iter()
-                    .map(|x| x.chars().collect())
+                    .map(|x| x.text.chars().collect())
                     .collect::<Vec<Vec<char>>>()[..],
             );
 
             if let Some(p) = common_prefix {
                 let s = p.iter().cloned().collect::<String>();
+                let word = cur_buf!(self).range(start, end);
 
                 if s.len() > word.len() && s.starts_with(&word[..]) {
-                    try!(self.delete_word_before_cursor(false));
-                    return self.insert_str_after_cursor(s.as_ref());
+                    return self.replace_range(start, end, &s);
                 }
             }
 
-            if self.show_completions_hint {
-                try!(write!(self.out, "\r\n"));
-                try!(self.print_completion_list(&completions[..]));
-                try!(write!(self.out, "\r\n"));
-                try!(self.display());
+            if self.show_completions_hint || self.context.completion_options.show_all_if_ambiguous {
+                if self.context.completion_options.menu_select {
+                    try!(self.start_menu(start, end, completions, 0));
+                } else {
+                    try!(write!(self.out, "\r\n"));
+                    try!(self.print_completion_list(&completions[..]));
+                    try!(write!(self.out, "\r\n"));
+                    try!(self.display());
+                }
 
                 self.show_completions_hint = false;
             } else {
@@ -310,6 +488,321 @@ impl<'a, W: Write> Editor<'a, W> {
         }
     }
 
+    /// The completions from the completer, ranked by their score and then sorted by their text,
+    /// with the range they replace limited to the buffer. None if there is no completer, or an
+    /// asynchronous completion was cancelled.
+    fn line_completions(&mut self) -> io::Result<Option<LineCompletions>> {
+        let (words, pos) = self.get_words_and_cursor_position();
+        let asynchronous = self.context.completion_options.asynchronous;
+
+        let mut res = match self.context.async_completer.clone() {
+            Some(completer) => if asynchronous {
+                match try!(self.complete_in_background(completer, words, pos)) {
+                    Some(res) => res,
+                    None => return Ok(None),
+                }
+            } else {
+                completer.complete_line(&self.completion_context(&words, pos))
+            },
+            None => match self.context.completer {
+                Some(ref completer) => {
+                    completer.complete_line(&self.completion_context(&words, pos))
+                }
+                None => return Ok(None),
+            },
+        };
+
+        res.end = cmp::min(res.end, cur_buf!(self).num_chars());
+        res.start = cmp::min(res.start, res.end);
+        res.completions.sort_by(|a, b| a.text.cmp(&b.text));
+        res.completions.dedup_by(|a, b| a.text == b.text);
+        // best first, keeping the order of those with the same score
+        res.completions.sort_by_key(|c| cmp::Reverse(c.score));
+        Ok(Some(res))
+    }
+
+    /// The line being edited, as passed to completers.
+    fn completion_context<'b>(
+        &'b self,
+        words: &'b [(usize, usize)],
+        pos: CursorPosition,
+    ) -> CompletionContext<'b> {
+        CompletionContext::new(cur_buf!(self), self.cursor, words, pos)
+            .with_ignore_case(self.context.completion_options.ignore_case)
+    }
+
+    /// Runs the completer on another thread, showing a spinner if it takes a while. None if it
+    /// times out or a key is pressed first, in which case it is left to finish on its own.
+    ///
+    /// Only one completer thread runs at a time. If a cancelled completion is still running, it
+    /// is reused if it completes the same line, and otherwise waited for before starting this one.
+    fn complete_in_background(
+        &mut self,
+        completer: Arc<Completer + Send + Sync>,
+        words: Vec<(usize, usize)>,
+        pos: CursorPosition,
+    ) -> io::Result<Option<LineCompletions>> {
+        let buffer = cur_buf!(self).clone();
+        let cursor = self.cursor;
+        let ignore_case = self.context.completion_options.ignore_case;
+        let spawn = || {
+            BackgroundCompletion::spawn(
+                completer.clone(),
+                buffer.clone(),
+                cursor,
+                words.clone(),
+                pos,
+                ignore_case,
+            )
+        };
+
+        let mut running = self.context.background_completion.take().unwrap_or_else(&spawn);
+        let mut is_current = running.completes(&completer, &buffer, cursor, ignore_case);
+
+        let started = Instant::now();
+        let mut next_frame = started + Duration::from_millis(SPINNER_DELAY_MS);
+        let res = loop {
+            match running.rx.recv_timeout(Duration::from_millis(COMPLETION_POLL_MS)) {
+                // the previous completion has finished, so this one can start
+                Ok(_) | Err(RecvTimeoutError::Disconnected) if !is_current => {
+                    running = spawn();
+                    is_current = true;
+                }
+                Ok(res) => break Some(res),
+                // the completer panicked
+                Err(RecvTimeoutError::Disconnected) => break None,
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+
+            let timed_out = self.context
+                .completion_options
+                .timeout
+                .map_or(false, |timeout| started.elapsed() >= timeout);
+            if timed_out || (self.key_pressed)() {
+                self.context.background_completion = Some(running);
+                break None;
+            }
+
+            if Instant::now() >= next_frame {
+                self.spinner = Some(self.spinner.map_or(0, |frame| frame + 1));
+                next_frame += Duration::from_millis(SPINNER_FRAME_MS);
+                try!(self.display());
+            }
+        };
+
+        if self.spinner.take().is_some() {
+            try!(self.display());
+        }
+        Ok(res)
+    }
+
+    /// Prints a hint for what to type below the buffer, where completions are listed.
+    fn print_hint(&mut self, hint: &str) -> io::Result<()> {
+        try!(write!(self.out, "\r\n{}\r\n", hint));
+        self.display()
+    }
+
+    /// Replaces the range `start..end` of the buffer with `text`, and moves the cursor after it.
+    fn replace_range(&mut self, start: usize, end: usize, text: &str) -> io::Result<()> {
+        cur_buf_mut!(self).remove(start, end);
+        self.cursor = start;
+        self.insert_str_after_cursor(text)
+    }
+
+    /// Replaces the range `start..end` of the buffer with the completion and its suffix.
+    fn insert_completion(
+        &mut self,
+        start: usize,
+        end: usize,
+        completion: &Completion,
+    ) -> io::Result<()> {
+        self.replace_range(start, end, &format!("{}{}", completion.text, completion.suffix))
+    }
+
+    /// Completes the word before the cursor by selecting from a menu of its completions. The
+    /// selected completion is shown in the buffer. If the menu is already shown, this selects
+    /// the next completion, or the previous one if `backward` is true.
+    pub fn menu_complete(
+        &mut self,
+        handler: &mut EventHandler<W>,
+        backward: bool,
+    ) -> io::Result<()> {
+        if self.menu.is_some() {
+            let movement = if backward { MenuMove::Previous } else { MenuMove::Next };
+            return self.move_menu_selection(movement);
+        }
+
+        handler(Event::new(self, EventKind::BeforeComplete));
+
+        let LineCompletions { start, end, completions, hint } = match try!(self.line_completions())
+        {
+            Some(completions) => completions,
+            None => return Ok(()),
+        };
+
+        self.show_completions_hint = false;
+        match completions.len() {
+            0 => match hint {
+                Some(hint) => self.print_hint(&hint),
+                None => Ok(()),
+            },
+            1 => self.insert_completion(start, end, &completions[0]),
+            n => self.start_menu(start, end, completions, if backward { n - 1 } else { 0 }),
+        }
+    }
+
+    fn start_menu(
+        &mut self,
+        start: usize,
+        end: usize,
+        completions: Vec<Completion>,
+        selected: usize,
+    ) -> io::Result<()> {
+        let original = cur_buf!(self).range_chars(start, end);
+
+        // the whole completion is undone at once
+        cur_buf_mut!(self).start_undo_group();
+        self.show_completions_hint = false;
+        self.menu = Some(CompletionMenu {
+            completions: completions,
+            selected: selected,
+            word_start: start,
+            inserted_len: original.len(),
+            original: original,
+            columns: 1,
+            page_size: 1,
+        });
+        self.replace_menu_word(true)
+    }
+
+    /// Returns true if completions are being selected from a menu.
+    pub fn is_menu_completing(&self) -> bool {
+        self.menu.is_some()
+    }
+
+    /// The completions in the menu and the index of the selected one, if the menu is shown.
+    pub fn menu_completions(&self) -> Option<(&[Completion], usize)> {
+        self.menu.as_ref().map(|menu| (&menu.completions[..], menu.selected))
+    }
+
+    /// Moves the selection in the completion menu. Moving past either end wraps around.
+    pub fn move_menu_selection(&mut self, movement: MenuMove) -> io::Result<()> {
+        {
+            let menu = match self.menu {
+                Some(ref mut menu) => menu,
+                None => return Ok(()),
+            };
+
+            let len = menu.completions.len();
+            let cols = menu.columns;
+            let i = menu.selected;
+            menu.selected = match movement {
+                MenuMove::Next => (i + 1) % len,
+                MenuMove::Previous => (i + len - 1) % len,
+                MenuMove::Down if i + cols < len => i + cols,
+                MenuMove::Down => i % cols,
+                MenuMove::Up if i >= cols => i - cols,
+                // the last entry in the same column
+                MenuMove::Up => i + (len - 1 - i) / cols * cols,
+                MenuMove::NextPage => cmp::min(i + menu.page_size, len - 1),
+                MenuMove::PreviousPage => i.saturating_sub(menu.page_size),
+            };
+        }
+
+        self.replace_menu_word(true)
+    }
+
+    /// Keeps the selected completion in the buffer, adds its suffix, and closes the completion
+    /// menu.
+    pub fn accept_menu_completion(&mut self) -> io::Result<()> {
+        let suffix = match self.menu.take() {
+            Some(menu) => menu.completions[menu.selected].suffix.clone(),
+            None => return self.display(),
+        };
+
+        try!(self.insert_str_after_cursor(&suffix));
+        cur_buf_mut!(self).end_undo_group();
+        self.display()
+    }
+
+    /// Restores the word as it was typed and closes the completion menu.
+    pub fn cancel_menu_completion(&mut self) -> io::Result<()> {
+        if self.menu.is_some() {
+            try!(self.replace_menu_word(false));
+            self.menu = None;
+            cur_buf_mut!(self).end_undo_group();
+        }
+        self.display()
+    }
+
+    /// Puts the selected completion in the buffer, or the original word if `selected` is false.
+    fn replace_menu_word(&mut self, selected: bool) -> io::Result<()> {
+        let (start, old_len, text) = match self.menu {
+            Some(ref mut menu) => {
+                let text = if selected {
+                    menu.completions[menu.selected].text.chars().collect()
+                } else {
+                    menu.original.clone()
+                };
+                let old_len = mem::replace(&mut menu.inserted_len, text.len());
+                (menu.word_start, old_len, text)
+            }
+            None => return Ok(()),
+        };
+
+        {
+            let buf = cur_buf_mut!(self);
+            buf.remove(start, start + old_len);
+            buf.insert(start, &text);
+        }
+        self.cursor = start + text.len();
+        self.display()
+    }
+
+    /// The lines of the completion menu to show below the buffer, with the selected completion
+    /// highlighted. Only the page with the selected completion is shown if they don't all fit
+    /// in the `rows` available.
+    fn menu_lines(&mut self, w: usize, rows: usize) -> Vec<String> {
+        let menu = match self.menu {
+            Some(ref mut menu) => menu,
+            None => return Vec::new(),
+        };
+
+        let len = menu.completions.len();
+        let (cols, col_width) = completion_columns(&menu.completions, w);
+        let total_rows = (len + cols - 1) / cols;
+        // leave a row to show which entries are on the page
+        let page_rows = if total_rows <= rows {
+            total_rows
+        } else {
+            cmp::max(1, rows.saturating_sub(1))
+        };
+        menu.columns = cols;
+        menu.page_size = page_rows * cols;
+
+        let first = menu.selected / menu.page_size * menu.page_size;
+        let last = cmp::min(first + menu.page_size, len);
+
+        let mut lines = Vec::new();
+        for row_start in (first..last).filter(|i| (i - first) % cols == 0) {
+            let mut line = String::new();
+            for i in row_start..cmp::min(row_start + cols, last) {
+                let entry = format_completion(&menu.completions[i], col_width, w);
+                if i == menu.selected {
+                    line.push_str(&format!("{}{}{}", style::Invert, entry, style::NoInvert));
+                } else {
+                    line.push_str(&entry);
+                }
+            }
+            lines.push(line);
+        }
+
+        if page_rows < total_rows {
+            lines.push(format!("{}-{} of {}", first + 1, last, len));
+        }
+        lines
+    }
+
     fn get_word_before_cursor(&self, ignore_space_before_cursor: bool) -> Option<(usize, usize)> {
         let (words, pos) = self.get_words_and_cursor_position();
         match pos {
@@ -334,17 +827,27 @@ impl<'a, W: Write> Editor<'a, W> {
     /// this method ignores that space until it finds a word.
     /// If `ignore_space_before_cursor` is false and there is space directly before the cursor,
     /// nothing is deleted.
+    /// The deleted word is added to the kill ring.
     pub fn delete_word_before_cursor(
         &mut self,
         ignore_space_before_cursor: bool,
     ) -> io::Result<()> {
-        if let Some((start, _)) = self.get_word_before_cursor(ignore_space_before_cursor) {
-            let moved = cur_buf_mut!(self).remove(start, self.cursor);
-            self.cursor -= moved;
-        }
+        let killed = self.remove_word_before_cursor(ignore_space_before_cursor);
+        self.kill(killed, true);
         self.display()
     }
 
+    fn remove_word_before_cursor(&mut self, ignore_space_before_cursor: bool) -> Vec<char> {
+        match self.get_word_before_cursor(ignore_space_before_cursor) {
+            Some((start, _)) => {
+                let removed = cur_buf!(self).range_chars(start, self.cursor);
+                self.cursor -= cur_buf_mut!(self).remove(start, self.cursor);
+                removed
+            }
+            None => Vec::new(),
+        }
+    }
+
     /// Clears the screen then prints the prompt and current buffer.
     pub fn clear(&mut self) -> io::Result<()> {
         try!(write!(self.out, "{}{}", clear::All, cursor::Goto(1, 1)));
@@ -406,92 +909,420 @@ impl<'a, W: Write> Editor<'a, W> {
         }
     }
 
-    /// Inserts a string directly after the cursor, moving the cursor to the right.
-    ///
-    /// Note: it is more efficient to call `insert_chars_after_cursor()` directly.
-    pub fn insert_str_after_cursor(&mut self, s: &str) -> io::Result<()> {
-        self.insert_chars_after_cursor(&s.chars().collect::<Vec<char>>()[..])
-    }
-
-    /// Inserts a character directly after the cursor, moving the cursor to the right.
-    pub fn insert_after_cursor(&mut self, c: char) -> io::Result<()> {
-        self.insert_chars_after_cursor(&[c])
+    /// Returns true if an incremental history search is in progress.
+    pub fn is_searching(&self) -> bool {
+        self.search.is_some()
     }
 
-    /// Inserts characters directly after the cursor, moving the cursor to the right.
-    pub fn insert_chars_after_cursor(&mut self, cs: &[char]) -> io::Result<()> {
-        {
-            let buf = cur_buf_mut!(self);
-            buf.insert(self.cursor, cs);
+    /// Starts an incremental history search in the given direction. If a search is already in
+    /// progress, moves to the next match in that direction instead.
+    ///
+    /// If the search query is empty, the query of the last search is reused.
+    pub fn search(&mut self, direction: SearchDirection) -> io::Result<()> {
+        let last_query = &self.last_search_query;
+        match self.search {
+            Some(ref mut search) => {
+                search.direction = direction;
+                if search.query.is_empty() {
+                    search.query = last_query.clone();
+                }
+            }
+            None => {
+                self.search = Some(Search {
+                    query: String::new(),
+                    direction: direction,
+                    failed: false,
+                    prompt: None,
+                    orig_history_loc: self.cur_history_loc,
+                    orig_cursor: self.cursor,
+                });
+                return self.display();
+            }
         }
 
-        self.cursor += cs.len();
+        self.find_search_match(true);
         self.display()
     }
 
-    /// Deletes the character directly before the cursor, moving the cursor to the left.
-    /// If the cursor is at the start of the line, nothing happens.
-    pub fn delete_before_cursor(&mut self) -> io::Result<()> {
-        if self.cursor > 0 {
-            let buf = cur_buf_mut!(self);
-            buf.remove(self.cursor - 1, self.cursor);
-            self.cursor -= 1;
-        }
-
+    /// Shows `prompt` on the line below the buffer and starts reading a history search query.
+    /// Unlike `search()`, nothing is searched for until the query is submitted with
+    /// `submit_search()`.
+    pub fn start_search_prompt<P: Into<String>>(
+        &mut self,
+        direction: SearchDirection,
+        prompt: P,
+    ) -> io::Result<()> {
+        self.search = Some(Search {
+            query: String::new(),
+            direction: direction,
+            failed: false,
+            prompt: Some(prompt.into()),
+            orig_history_loc: self.cur_history_loc,
+            orig_cursor: self.cursor,
+        });
         self.display()
     }
 
-    /// Deletes the character directly after the cursor. The cursor does not move.
-    /// If the cursor is at the end of the line, nothing happens.
-    pub fn delete_after_cursor(&mut self) -> io::Result<()> {
-        {
-            let buf = cur_buf_mut!(self);
+    /// The query of the current history search, if a search is in progress.
+    pub fn search_query(&self) -> Option<&str> {
+        self.search.as_ref().map(|s| &s.query[..])
+    }
 
-            if self.cursor < buf.num_chars() {
-                buf.remove(self.cursor, self.cursor + 1);
+    /// Appends a character to the query of the current history search.
+    pub fn search_insert_char(&mut self, c: char) -> io::Result<()> {
+        let incremental = match self.search {
+            Some(ref mut search) => {
+                search.query.push(c);
+                search.prompt.is_none()
             }
+            None => false,
+        };
+
+        if incremental {
+            self.find_search_match(false);
         }
         self.display()
     }
 
-    /// Deletes every character preceding the cursor until the beginning of the line.
-    pub fn delete_all_before_cursor(&mut self) -> io::Result<()> {
-        cur_buf_mut!(self).remove(0, self.cursor);
-        self.cursor = 0;
+    /// Removes the last character from the query of the current history search. An incremental
+    /// search then searches again from where the search started.
+    pub fn search_delete_before_cursor(&mut self) -> io::Result<()> {
+        let incremental = match self.search {
+            Some(ref mut search) => {
+                search.query.pop();
+                search.prompt.is_none()
+            }
+            None => false,
+        };
+
+        if incremental {
+            if let Some(ref search) = self.search {
+                self.cur_history_loc = search.orig_history_loc;
+                self.cursor = search.orig_cursor;
+            }
+            self.find_search_match(false);
+        }
         self.display()
     }
 
-    /// Deletes every character after the cursor until the end of the line.
-    pub fn delete_all_after_cursor(&mut self) -> io::Result<()> {
-        {
-            let buf = cur_buf_mut!(self);
-            buf.truncate(self.cursor);
+    /// Ends a search started with `start_search_prompt()` and moves to the next history entry
+    /// containing the query, or the query of the last search if the query is empty.
+    ///
+    /// Returns `Ok(false)` if no entry matched, in which case the editor is left as it was.
+    pub fn submit_search(&mut self) -> io::Result<bool> {
+        let last_query = &self.last_search_query;
+        if let Some(ref mut search) = self.search {
+            if search.query.is_empty() {
+                search.query = last_query.clone();
+            }
         }
-        self.display()
+
+        self.finish_search_prompt()
     }
 
-    /// Deletes every character from the cursor until the given position.
-    pub fn delete_until(&mut self, position: usize) -> io::Result<()> {
-        {
-            let buf = cur_buf_mut!(self);
-            buf.remove(
-                cmp::min(self.cursor, position),
-                cmp::max(self.cursor, position),
-            );
-            self.cursor = cmp::min(self.cursor, position);
+    /// Moves to the next history entry in `direction` which contains the query of the last
+    /// search. Returns `Ok(false)` if there is no such entry.
+    pub fn search_again(&mut self, direction: SearchDirection) -> io::Result<bool> {
+        self.search = Some(Search {
+            query: self.last_search_query.clone(),
+            direction: direction,
+            failed: false,
+            prompt: Some(String::new()),
+            orig_history_loc: self.cur_history_loc,
+            orig_cursor: self.cursor,
+        });
+
+        self.finish_search_prompt()
+    }
+
+    fn finish_search_prompt(&mut self) -> io::Result<bool> {
+        let found = if self.search_query().map_or(false, |q| !q.is_empty()) {
+            self.find_search_match(true);
+            self.search.as_ref().map_or(false, |s| !s.failed)
+        } else {
+            false
+        };
+
+        if found {
+            self.accept_search()?;
+            self.move_cursor_to_end_of_line()?;
+        } else {
+            self.abort_search()?;
+        }
+        Ok(found)
+    }
+
+    /// Ends the current history search, leaving the matched entry in the editor.
+    pub fn accept_search(&mut self) -> io::Result<()> {
+        if let Some(search) = self.search.take() {
+            if !search.query.is_empty() {
+                self.last_search_query = search.query;
+            }
         }
         self.display()
     }
 
-    /// Deletes every character from the cursor until the given position, inclusive.
-    pub fn delete_until_inclusive(&mut self, position: usize) -> io::Result<()> {
-        {
-            let buf = cur_buf_mut!(self);
-            buf.remove(
-                cmp::min(self.cursor, position),
-                cmp::max(self.cursor + 1, position + 1),
-            );
-            self.cursor = cmp::min(self.cursor, position);
+    /// Ends the current history search, returning to the buffer and cursor position from before
+    /// the search was started.
+    pub fn abort_search(&mut self) -> io::Result<()> {
+        if let Some(search) = self.search.take() {
+            self.cur_history_loc = search.orig_history_loc;
+            self.cursor = search.orig_cursor;
+        }
+        self.display()
+    }
+
+    /// Moves to the next history entry in `direction` which starts with the text before the
+    /// cursor, leaving the cursor where it is. Does nothing if there is no such entry.
+    pub fn search_history_prefix(&mut self, direction: SearchDirection) -> io::Result<()> {
+        let prefix = Buffer::from(cur_buf!(self).range_chars(0, self.cursor)
+            .into_iter()
+            .collect::<String>());
+        let history = &self.context.history;
+        let found = match (direction, self.cur_history_loc) {
+            (SearchDirection::Backward, loc) => {
+                (0..loc.unwrap_or(history.len())).rev().find(|&i| history[i].starts_with(&prefix))
+            }
+            (SearchDirection::Forward, Some(loc)) => {
+                (loc + 1..history.len()).find(|&i| history[i].starts_with(&prefix))
+            }
+            (SearchDirection::Forward, None) => None,
+        };
+
+        if found.is_some() {
+            self.cur_history_loc = found;
+        }
+        self.display()
+    }
+
+    /// Moves to the next match of the current search query. If `skip_current` is true, this is
+    /// the next match after the cursor in the current entry, or else the first match in the next
+    /// history entry. If `skip_current` is false, the current entry is matched from its start.
+    fn find_search_match(&mut self, skip_current: bool) {
+        let (query, direction) = match self.search {
+            Some(ref search) => (search.query.chars().collect::<Vec<char>>(), search.direction),
+            None => return,
+        };
+
+        if query.is_empty() {
+            if let Some(ref mut search) = self.search {
+                search.failed = false;
+            }
+            return;
+        }
+
+        if skip_current {
+            let next = self.cur_history_loc.and_then(|i| {
+                self.context.history[i].find_from(&query, self.cursor + 1)
+            });
+            if let Some(pos) = next {
+                self.cursor = pos;
+                if let Some(ref mut search) = self.search {
+                    search.failed = false;
+                }
+                return;
+            }
+        }
+
+        let start = match (direction, self.cur_history_loc) {
+            (SearchDirection::Backward, Some(i)) if skip_current => i.checked_sub(1),
+            (SearchDirection::Forward, Some(i)) if skip_current => Some(i + 1),
+            (_, Some(i)) => Some(i),
+            (SearchDirection::Backward, None) => self.context.history.len().checked_sub(1),
+            // The new buffer is the newest entry, there is nothing after it.
+            (SearchDirection::Forward, None) => None,
+        };
+
+        let found = start.and_then(|start| self.context.history.search(start, direction, &query));
+        if let Some(i) = found {
+            self.cur_history_loc = Some(i);
+            self.cursor = self.context.history[i].find(&query).unwrap_or(0);
+        }
+
+        if let Some(ref mut search) = self.search {
+            search.failed = found.is_none();
+        }
+    }
+
+    /// The range of chars in the current buffer that matches the search query, if any.
+    fn search_match_range(&self) -> Option<(usize, usize)> {
+        match self.search {
+            Some(ref search) if search.prompt.is_none() && !search.failed &&
+                                !search.query.is_empty() => {
+                let len = search.query.chars().count();
+                Some((self.cursor, self.cursor + len))
+            }
+            _ => None,
+        }
+    }
+
+    /// Inserts a string directly after the cursor, moving the cursor to the right.
+    ///
+    /// Note: it is more efficient to call `insert_chars_after_cursor()` directly.
+    pub fn insert_str_after_cursor(&mut self, s: &str) -> io::Result<()> {
+        self.insert_chars_after_cursor(&s.chars().collect::<Vec<char>>()[..])
+    }
+
+    /// Inserts a character directly after the cursor, moving the cursor to the right.
+    pub fn insert_after_cursor(&mut self, c: char) -> io::Result<()> {
+        self.insert_chars_after_cursor(&[c])
+    }
+
+    /// Inserts characters directly after the cursor, moving the cursor to the right.
+    pub fn insert_chars_after_cursor(&mut self, cs: &[char]) -> io::Result<()> {
+        {
+            let buf = cur_buf_mut!(self);
+            buf.insert(self.cursor, cs);
+        }
+
+        self.cursor += cs.len();
+        self.display()
+    }
+
+    /// Inserts pasted text directly after the cursor, as a single change which can be undone.
+    /// If a search is in progress, it is accepted first.
+    pub fn paste(&mut self, text: &str) -> io::Result<()> {
+        if self.is_searching() {
+            self.accept_search()?;
+        }
+
+        self.insert_str_after_cursor(text)
+    }
+
+    /// Deletes the character directly before the cursor, moving the cursor to the left.
+    /// If the cursor is at the start of the line, nothing happens.
+    pub fn delete_before_cursor(&mut self) -> io::Result<()> {
+        if self.cursor > 0 {
+            let buf = cur_buf_mut!(self);
+            buf.remove(self.cursor - 1, self.cursor);
+            self.cursor -= 1;
+        }
+
+        self.display()
+    }
+
+    /// Deletes the character directly after the cursor. The cursor does not move.
+    /// If the cursor is at the end of the line, nothing happens.
+    pub fn delete_after_cursor(&mut self) -> io::Result<()> {
+        {
+            let buf = cur_buf_mut!(self);
+
+            if self.cursor < buf.num_chars() {
+                buf.remove(self.cursor, self.cursor + 1);
+            }
+        }
+        self.display()
+    }
+
+    /// Deletes every character preceding the cursor until the beginning of the line.
+    pub fn delete_all_before_cursor(&mut self) -> io::Result<()> {
+        let killed = cur_buf!(self).range_chars(0, self.cursor);
+        cur_buf_mut!(self).remove(0, self.cursor);
+        self.cursor = 0;
+        self.kill(killed, true);
+        self.display()
+    }
+
+    /// Deletes every character after the cursor until the end of the line.
+    /// The deleted text is added to the kill ring.
+    pub fn delete_all_after_cursor(&mut self) -> io::Result<()> {
+        let killed = {
+            let buf = cur_buf_mut!(self);
+            let killed = buf.range_chars(self.cursor, buf.num_chars());
+            buf.truncate(self.cursor);
+            killed
+        };
+        self.kill(killed, false);
+        self.display()
+    }
+
+    /// Adds killed text to the kill ring. If the previous key killed text too, the text is added
+    /// to that kill, in front of it if it was killed backwards.
+    fn kill(&mut self, text: Vec<char>, backward: bool) {
+        let merge = self.last_key_action == Some(KeyAction::Kill)
+            || self.key_action == Some(KeyAction::Kill);
+        self.key_action = Some(KeyAction::Kill);
+
+        if text.is_empty() {
+            return;
+        }
+
+        let text: String = text.into_iter().collect();
+        let ring = &mut self.context.kill_ring;
+        if !merge {
+            ring.push(text);
+        } else if backward {
+            ring.prepend(&text);
+        } else {
+            ring.append(&text);
+        }
+    }
+
+    /// Inserts the newest text in the kill ring at the cursor.
+    pub fn yank(&mut self) -> io::Result<()> {
+        let text: Vec<char> = match self.context.kill_ring.yank() {
+            Some(text) => text.chars().collect(),
+            None => return Ok(()),
+        };
+
+        let start = self.cursor;
+        self.insert_chars_after_cursor(&text)?;
+        self.key_action = Some(KeyAction::Yank(start, self.cursor));
+        Ok(())
+    }
+
+    /// Replaces the text inserted by the previous key's yank with the kill before it in the kill
+    /// ring. Does nothing if the previous key did not yank.
+    pub fn yank_pop(&mut self) -> io::Result<()> {
+        let (start, end) = match self.last_key_action {
+            Some(KeyAction::Yank(start, end)) => (start, end),
+            _ => return Ok(()),
+        };
+        let text: Vec<char> = match self.context.kill_ring.rotate() {
+            Some(text) => text.chars().collect(),
+            None => return Ok(()),
+        };
+
+        {
+            let buf = cur_buf_mut!(self);
+            buf.start_undo_group();
+            buf.remove(start, end);
+            buf.insert(start, &text);
+            buf.end_undo_group();
+        }
+        self.cursor = start + text.len();
+        self.key_action = Some(KeyAction::Yank(start, self.cursor));
+        self.display()
+    }
+
+    /// Called before each key is handled. Consecutive kills are only merged, and yank-pop only
+    /// works straight after a yank, if nothing else happens in between.
+    pub fn start_key(&mut self) {
+        self.last_key_action = self.key_action.take();
+    }
+
+    /// Deletes every character from the cursor until the given position.
+    pub fn delete_until(&mut self, position: usize) -> io::Result<()> {
+        {
+            let buf = cur_buf_mut!(self);
+            buf.remove(
+                cmp::min(self.cursor, position),
+                cmp::max(self.cursor, position),
+            );
+            self.cursor = cmp::min(self.cursor, position);
+        }
+        self.display()
+    }
+
+    /// Deletes every character from the cursor until the given position, inclusive.
+    pub fn delete_until_inclusive(&mut self, position: usize) -> io::Result<()> {
+        {
+            let buf = cur_buf_mut!(self);
+            buf.remove(
+                cmp::min(self.cursor, position),
+                cmp::max(self.cursor + 1, position + 1),
+            );
+            self.cursor = cmp::min(self.cursor, position);
         }
         self.display()
     }
@@ -581,20 +1412,137 @@ impl<'a, W: Write> Editor<'a, W> {
         self.move_cursor_to_end_of_line()
     }
 
+    /// Accepts the autosuggestion up to the end of its next word. Words are found with
+    /// `Context::word_divider_fn`.
+    pub fn accept_autosuggestion_word(&mut self) -> io::Result<()> {
+        let end = match self.autosuggestion {
+            Some(ref suggestion) => {
+                let len = cur_buf!(self).num_chars();
+                (self.context.word_divider_fn)(suggestion)
+                    .into_iter()
+                    .map(|(_, end)| end)
+                    .find(|&end| end > len)
+                    .unwrap_or_else(|| suggestion.num_chars())
+            }
+            None => return Ok(()),
+        };
+        self.accept_autosuggestion_until(end)
+    }
+
+    /// Accepts the next character of the autosuggestion.
+    pub fn accept_autosuggestion_char(&mut self) -> io::Result<()> {
+        let end = cur_buf!(self).num_chars() + 1;
+        self.accept_autosuggestion_until(end)
+    }
+
+    /// Copies the autosuggestion up to `end` into the current buffer.
+    fn accept_autosuggestion_until(&mut self, end: usize) -> io::Result<()> {
+        let text = match self.autosuggestion {
+            Some(ref suggestion) => {
+                let len = cur_buf!(self).num_chars();
+                suggestion.range_chars(len, cmp::min(end, suggestion.num_chars()))
+            }
+            None => return Ok(()),
+        };
+
+        {
+            let buf = cur_buf_mut!(self);
+            let len = buf.num_chars();
+            buf.insert(len, &text);
+        }
+        self.move_cursor_to_end_of_line()
+    }
+
+    /// The autosuggestion currently shown, which starts with the current buffer.
     pub fn current_autosuggestion(&self) -> Option<&Buffer> {
-        if self.show_autosuggestions {
-            self.context
-                .history
-                .get_newest_match(self.cur_history_loc, self.current_buffer())
-        } else {
-            None
+        self.autosuggestion.as_ref()
+    }
+
+    /// Whether to show autosuggestions for this line. True by default.
+    pub fn show_autosuggestions(&self) -> bool {
+        self.show_autosuggestions
+    }
+
+    pub fn set_show_autosuggestions(&mut self, show: bool) -> io::Result<()> {
+        self.show_autosuggestions = show;
+        self.display()
+    }
+
+    /// Asks `Context::autosuggestion_provider` for a suggestion for the current buffer.
+    fn update_autosuggestion(&mut self) {
+        self.autosuggestion = match self.context.autosuggestion_provider {
+            Some(ref provider) if self.show_autosuggestions && self.search.is_none() &&
+                self.selection.is_none() =>
+            {
+                let buf = cur_buf!(self);
+                provider
+                    .suggest(buf, &self.context.history, self.cur_history_loc)
+                    .filter(|s| s.num_chars() > buf.num_chars() && s.starts_with(buf))
+            }
+            _ => None,
+        };
+    }
+
+    /// Starts selecting text at the cursor, as in Vi's visual mode. The selection is highlighted.
+    ///
+    /// If `linewise` is true, whole lines are selected. If text is already being selected, only
+    /// this changes.
+    pub fn start_selection(&mut self, linewise: bool) -> io::Result<()> {
+        let anchor = self.selection.map_or(self.cursor, |(anchor, _)| anchor);
+        self.selection = Some((anchor, linewise));
+        self.display()
+    }
+
+    pub fn clear_selection(&mut self) -> io::Result<()> {
+        self.selection = None;
+        self.display()
+    }
+
+    /// The range of selected characters, with the end exclusive. The selection goes from where it
+    /// was started up to and including the character under the cursor.
+    pub fn selection(&self) -> Option<(usize, usize)> {
+        let (anchor, linewise) = self.selection?;
+        let buf = cur_buf!(self);
+        let num_chars = buf.num_chars();
+
+        let anchor = cmp::min(anchor, num_chars);
+        let start = cmp::min(anchor, self.cursor);
+        let end = cmp::min(cmp::max(anchor, self.cursor) + 1, num_chars);
+        if !linewise {
+            return Some((start, end));
         }
+
+        // extend the selection to the start and end of the lines, including the last newline
+        let start = (0..start)
+            .rev()
+            .find(|&i| buf.char_after(i) == Some('\n'))
+            .map_or(0, |i| i + 1);
+        let end = (end.saturating_sub(1)..num_chars)
+            .find(|&i| buf.char_after(i) == Some('\n'))
+            .map_or(num_chars, |i| i + 1);
+        Some((start, end))
     }
 
     pub fn is_currently_showing_autosuggestion(&self) -> bool {
         self.current_autosuggestion().is_some()
     }
 
+    /// Returns the buffer text colored by `Context::highlighter`, if there is one.
+    fn highlight_buffer(&self, text: &str) -> Option<String> {
+        let highlighted = match self.context.highlighter {
+            Some(ref highlighter) => highlighter.highlight(text, self.cursor),
+            None => return None,
+        };
+
+        // The cursor is placed using the width of the buffer text, so the highlighted text must
+        // take up the same space on every line.
+        if highlighted.split('\n').map(util::width).eq(text.split('\n').map(util::width)) {
+            Some(highlighted)
+        } else {
+            None
+        }
+    }
+
     fn _display(&mut self, show_autosuggest: bool) -> io::Result<()> {
         fn calc_width(prompt_width: usize, buf_widths: Vec<usize>, terminal_width: usize) -> usize {
             let mut total = 0;
@@ -610,21 +1558,34 @@ impl<'a, W: Write> Editor<'a, W> {
             total
         }
 
-        let (w, _) =
-            // when testing hardcode terminal size values
-            if cfg!(test) { (80, 24) }
-            // otherwise pull values from termion
-            else {
-                let (mut size_col, mut size_row) = try!(termion::terminal_size());
-                if size_col == 0 {
-                    size_col = 80;
-                    size_row = 24;
-                }
-                (size_col, size_row)
-            };
-        let w = w as usize;
+        let (w, h) = try!(terminal_size());
+
+        let prompt = match self.search {
+            Some(ref search) if search.prompt.is_none() => format!(
+                "({}{})`{}': ",
+                if search.failed { "failed " } else { "" },
+                match search.direction {
+                    SearchDirection::Forward => "i-search",
+                    SearchDirection::Backward => "reverse-i-search",
+                },
+                search.query
+            ),
+            _ => self.prompt.clone(),
+        };
+        let search_prompt = match self.search {
+            Some(Search { prompt: Some(ref prompt), ref query, .. }) => {
+                Some(format!("{}{}", prompt, query))
+            }
+            _ => None,
+        };
+        if show_autosuggest {
+            self.update_autosuggestion();
+        } else {
+            self.autosuggestion = None;
+        }
 
-        let prompt_width = util::width(&self.prompt);
+        let prompt_width = util::width(&prompt);
+        let highlight = self.search_match_range().or_else(|| self.selection());
         let buf = cur_buf!(self);
         let buf_width = buf.width();
 
@@ -667,46 +1628,35 @@ impl<'a, W: Write> Editor<'a, W> {
             ));
         }
         // Move the cursor to the start of the line then clear everything after. Write the prompt
-        try!(write!(self.out, "\r{}{}", clear::AfterCursor, self.prompt));
+        try!(write!(self.out, "\r{}{}", clear::AfterCursor, prompt));
 
-        // If we have an autosuggestion, we make the autosuggestion the buffer we print out.
-        // We get the number of bytes in the buffer (but NOT the autosuggestion).
-        // Then, we loop and subtract from that number until it's 0, in which case we are printing
-        // the autosuggestion from here on (in a different color).
-        let lines = if show_autosuggest {
-            match self.current_autosuggestion() {
-                Some(suggestion) => suggestion.lines(),
-                None => buf.lines(),
-            }
-        } else {
-            buf.lines()
-        };
-        let mut buf_num_remaining_bytes = buf.num_bytes();
+        let buf_text = buf.range(0, buf_num_chars);
+        let highlighted = self.highlight_buffer(&buf_text);
+        let mut text = highlighted.as_ref().unwrap_or(&buf_text).clone();
 
-        for (i, line) in lines.iter().enumerate() {
-            if i > 0 {
-                try!(write!(self.out, "{}", cursor::Right(prompt_width as u16)));
+        if let Some((start, end)) = highlight {
+            // Highlight the part of the buffer which matches the search query, or the selection.
+            // There is no autosuggestion then.
+            text = invert_range(&text, start, end);
+        } else {
+            // The rest of the autosuggestion is printed after the buffer, in a different color.
+            if let Some(suggestion) = self.current_autosuggestion() {
+                text.push_str(&color::Fg(color::Yellow).to_string());
+                text.push_str(&suggestion.range(buf_num_chars, suggestion.num_chars()));
+                text.push_str(&color::Fg(color::Reset).to_string());
             }
+        }
 
-            if buf_num_remaining_bytes == 0 {
-                write!(self.out, "{}", line)?;
-            } else if line.len() > buf_num_remaining_bytes {
-                write!(self.out, "{}", &line[..buf_num_remaining_bytes])?;
-                write!(self.out, "{}", color::Fg(color::Yellow))?;
-                write!(self.out, "{}", &line[buf_num_remaining_bytes..])?;
-                buf_num_remaining_bytes = 0;
-            } else {
-                buf_num_remaining_bytes -= line.len();
-                write!(self.out, "{}", line)?;
-            }
+        if highlighted.is_some() {
+            text.push_str(&style::Reset.to_string());
+        }
 
-            if i + 1 < lines.len() {
-                write!(self.out, "\r\n")?;
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                try!(write!(self.out, "\r\n{}", cursor::Right(prompt_width as u16)));
             }
-        }
 
-        if self.is_currently_showing_autosuggestion() {
-            write!(self.out, "{}", color::Fg(color::Reset))?;
+            write!(self.out, "{}", line)?;
         }
 
         // at the end of the line, move the cursor down a line
@@ -714,6 +1664,35 @@ impl<'a, W: Write> Editor<'a, W> {
             try!(write!(self.out, "\r\n"));
         }
 
+        // The completion menu, or the spinner while completing in the background, goes below the
+        // buffer, then the term cursor goes back to the end of the buffer.
+        let mut menu_lines = self.menu_lines(w, h.saturating_sub(new_num_lines));
+        if let Some(frame) = self.spinner {
+            menu_lines.push(format!("{} completing...", SPINNER[frame % SPINNER.len()]));
+        }
+        if !menu_lines.is_empty() {
+            for line in &menu_lines {
+                try!(write!(self.out, "\r\n{}", line));
+            }
+            try!(write!(self.out, "{}\r", cursor::Up(menu_lines.len() as u16)));
+            if new_total_width % w != 0 {
+                try!(write!(self.out, "{}", cursor::Right((new_total_width % w) as u16)));
+            }
+        }
+
+        // A search prompt goes on the line below the buffer, and the term cursor stays there.
+        if let Some(search_prompt) = search_prompt {
+            let mut prompt_line = new_num_lines;
+            if new_total_width % w != 0 {
+                try!(write!(self.out, "\r\n"));
+                prompt_line += 1;
+            }
+            try!(write!(self.out, "{}", search_prompt));
+
+            self.term_cursor_line = prompt_line + util::width(&search_prompt) / w;
+            return self.out.flush();
+        }
+
         self.term_cursor_line = (new_total_width_to_cursor + w) / w;
 
         // The term cursor is now on the bottom line. We may need to move the term cursor up
@@ -757,10 +1736,121 @@ impl<'a, W: Write> From<Editor<'a, W>> for String {
     }
 }
 
+/// The terminal size in columns and rows.
+fn terminal_size() -> io::Result<(usize, usize)> {
+    // when testing hardcode terminal size values
+    if cfg!(test) {
+        return Ok((80, 24));
+    }
+
+    let (mut w, mut h) = try!(termion::terminal_size());
+    if w == 0 {
+        w = 80;
+        h = 24;
+    }
+    Ok((w as usize, h as usize))
+}
+
+/// The number of columns to list `completions` in on a terminal `w` columns wide, and the width
+/// of each column. Completions with descriptions are listed one per line, with the descriptions
+/// lined up after the widest completion.
+fn completion_columns(completions: &[Completion], w: usize) -> (usize, usize) {
+    let max_word_size = completions
+        .iter()
+        .fold(1, |m, x| cmp::max(m, util::width(x.display_text())));
+    if completions.iter().any(|c| c.description.is_some()) {
+        return (1, max_word_size + 2);
+    }
+
+    let cols = cmp::max(1, w / max_word_size);
+    let col_width = 2 + w / cols;
+    (cmp::max(1, w / col_width), col_width)
+}
+
+/// Whether there is input on stdin which hasn't been read yet.
+#[cfg(unix)]
+fn stdin_has_input() -> bool {
+    let mut fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    unsafe { libc::poll(&mut fd, 1, 0) > 0 }
+}
+
+#[cfg(not(unix))]
+fn stdin_has_input() -> bool {
+    false
+}
+
+/// The completion as listed in a column `col_width` wide, followed by its description cut to fit
+/// on a terminal `w` columns wide.
+fn format_completion(completion: &Completion, col_width: usize, w: usize) -> String {
+    let mut s = pad_to_width(completion.display_text(), col_width);
+    if let Some(ref description) = completion.description {
+        s.push_str(util::truncate_to_width(description, w.saturating_sub(col_width + 1)));
+    }
+    s
+}
+
+/// Pads `s` with spaces to take up `width` columns on the terminal.
+fn pad_to_width(s: &str, width: usize) -> String {
+    let padding = width.saturating_sub(util::width(s));
+    format!("{}{}", s, " ".repeat(padding))
+}
+
+/// Inverts the colors of the printed characters `start..end` of `text`, which may contain ANSI
+/// escape codes. The inversion is repeated after codes in the range, in case they reset it.
+fn invert_range(text: &str, start: usize, end: usize) -> String {
+    let invert = style::Invert.to_string();
+    let no_invert = style::NoInvert.to_string();
+    let mut res = String::new();
+    let mut state = util::AnsiState::Norm;
+    let mut i = 0;
+
+    for c in text.chars() {
+        if util::ansi_step(&mut state, c) {
+            if i == start {
+                res.push_str(&invert);
+            }
+            if i == end {
+                res.push_str(&no_invert);
+            }
+            res.push(c);
+            i += 1;
+        } else {
+            res.push(c);
+            if let util::AnsiState::Norm = state {
+                if start < i && i < end {
+                    res.push_str(&invert);
+                }
+            }
+        }
+    }
+
+    if end >= i {
+        res.push_str(&no_invert);
+    }
+    res
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use BasicCompleter;
+    use Completer;
+    use CompletionContext;
+    use LineCompletions;
+    use MatchStrategy;
+    use ArgSpec;
+    use CommandSpec;
+    use GrammarCompleter;
+    use ValueSpec;
     use Context;
+    use History;
 
     #[test]
     /// test undoing delete_all_after_cursor
@@ -855,4 +1945,529 @@ mod tests {
         assert_eq!(ed.cursor, 1);
         assert_eq!(String::from(ed), "r");
     }
+
+    #[test]
+    fn selection() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        ed.insert_str_after_cursor("ab\ncd\nef").unwrap();
+        ed.move_cursor_to(4).unwrap();
+        assert_eq!(ed.selection(), None);
+
+        ed.start_selection(false).unwrap();
+        assert_eq!(ed.selection(), Some((4, 5)));
+        ed.move_cursor_left(3).unwrap();
+        assert_eq!(ed.selection(), Some((1, 5)));
+
+        ed.start_selection(true).unwrap();
+        assert_eq!(ed.selection(), Some((0, 6)));
+        ed.move_cursor_to_end_of_line().unwrap();
+        assert_eq!(ed.selection(), Some((3, 8)));
+
+        ed.clear_selection().unwrap();
+        assert_eq!(ed.selection(), None);
+    }
+
+    #[test]
+    /// the selection is shown inverted
+    fn selection_highlight() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        ed.insert_str_after_cursor("abc").unwrap();
+        ed.move_cursor_left(2).unwrap();
+        ed.start_selection(false).unwrap();
+        ed.out.clear();
+
+        ed.display().unwrap();
+        let output = String::from_utf8(ed.out.clone()).unwrap();
+        assert!(output.contains(&format!("a{}b{}c", style::Invert, style::NoInvert)));
+    }
+
+    #[test]
+    fn highlighter() {
+        let mut context = Context::new();
+        context.highlighter = Some(Box::new(|text: &str, _| {
+            text.replace("ls", &format!("{}ls{}", style::Bold, style::Reset))
+        }));
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        ed.insert_str_after_cursor("ls -l").unwrap();
+        ed.out.clear();
+
+        ed.display().unwrap();
+        let output = String::from_utf8(ed.out.clone()).unwrap();
+        assert!(output.contains(&format!("{}ls{} -l{}", style::Bold, style::Reset, style::Reset)));
+    }
+
+    #[test]
+    fn highlighter_changing_width() {
+        let mut context = Context::new();
+        context.highlighter = Some(Box::new(|text: &str, _| format!("{}!", text)));
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        ed.insert_str_after_cursor("abc").unwrap();
+        ed.out.clear();
+
+        ed.display().unwrap();
+        let output = String::from_utf8(ed.out.clone()).unwrap();
+        assert!(output.contains("abc"));
+        assert!(!output.contains("abc!"));
+    }
+
+    #[test]
+    fn selection_with_highlighter() {
+        let mut context = Context::new();
+        context.highlighter = Some(Box::new(|text: &str, _| {
+            text.replace("b", &format!("{}b{}", style::Bold, style::Reset))
+        }));
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        ed.insert_str_after_cursor("abc").unwrap();
+        ed.move_cursor_left(3).unwrap();
+        ed.start_selection(false).unwrap();
+        ed.move_cursor_right(2).unwrap();
+        ed.out.clear();
+
+        ed.display().unwrap();
+        let output = String::from_utf8(ed.out.clone()).unwrap();
+        assert!(output.contains(&format!(
+            "{}a{}{}b{}{}c{}",
+            style::Invert,
+            style::Bold,
+            style::Invert,
+            style::Reset,
+            style::Invert,
+            style::NoInvert
+        )));
+    }
+
+    #[test]
+    fn history_autosuggestion() {
+        let mut context = Context::new();
+        context.history.push("git commit".into()).unwrap();
+        context.history.push("git push".into()).unwrap();
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+
+        ed.insert_str_after_cursor("git").unwrap();
+        let suggestion = ed.current_autosuggestion().cloned().map(String::from);
+        assert_eq!(suggestion, Some("git push".into()));
+        ed.insert_str_after_cursor(" c").unwrap();
+        let suggestion = ed.current_autosuggestion().cloned().map(String::from);
+        assert_eq!(suggestion, Some("git commit".into()));
+
+        ed.set_show_autosuggestions(false).unwrap();
+        assert!(!ed.is_currently_showing_autosuggestion());
+        ed.accept_autosuggestion().unwrap();
+        assert_eq!(String::from(ed), "git c");
+    }
+
+    #[test]
+    fn custom_autosuggestion_provider() {
+        let mut context = Context::new();
+        context.history.push("git commit".into()).unwrap();
+        context.autosuggestion_provider = Some(Box::new(|buf: &Buffer, _: &History, _| {
+            let text = String::from(buf.clone());
+            if text.ends_with("-") {
+                Some(format!("{}-help", text).into())
+            } else if text == "ls" {
+                // not an extension of the buffer, so never shown
+                Some("cd".into())
+            } else if text == "pwd" {
+                // no longer than the buffer, so never shown
+                Some("pwd".into())
+            } else {
+                None
+            }
+        }));
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+
+        ed.insert_str_after_cursor("git").unwrap();
+        assert!(!ed.is_currently_showing_autosuggestion());
+        ed.insert_str_after_cursor(" -").unwrap();
+        ed.accept_autosuggestion().unwrap();
+        assert_eq!(String::from(ed.current_buffer().clone()), "git --help");
+
+        ed.move_cursor_to_start_of_line().unwrap();
+        ed.delete_all_after_cursor().unwrap();
+        ed.insert_str_after_cursor("ls").unwrap();
+        assert!(!ed.is_currently_showing_autosuggestion());
+
+        ed.move_cursor_to_start_of_line().unwrap();
+        ed.delete_all_after_cursor().unwrap();
+        ed.insert_str_after_cursor("pwd").unwrap();
+        assert!(!ed.is_currently_showing_autosuggestion());
+    }
+
+    #[test]
+    fn no_autosuggestion_provider() {
+        let mut context = Context::new();
+        context.history.push("git commit".into()).unwrap();
+        context.autosuggestion_provider = None;
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+
+        ed.insert_str_after_cursor("git").unwrap();
+        assert!(!ed.is_currently_showing_autosuggestion());
+    }
+
+    #[test]
+    fn accept_autosuggestion_partially() {
+        let mut context = Context::new();
+        context.history.push("git commit -m fix".into()).unwrap();
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+
+        ed.insert_str_after_cursor("git c").unwrap();
+        ed.accept_autosuggestion_word().unwrap();
+        assert_eq!(String::from(ed.current_buffer().clone()), "git commit");
+        ed.accept_autosuggestion_word().unwrap();
+        assert_eq!(String::from(ed.current_buffer().clone()), "git commit -m");
+        ed.accept_autosuggestion_char().unwrap();
+        ed.accept_autosuggestion_char().unwrap();
+        assert_eq!(ed.cursor(), 15);
+        assert_eq!(String::from(ed.current_buffer().clone()), "git commit -m f");
+
+        ed.undo().unwrap();
+        assert_eq!(String::from(ed.current_buffer().clone()), "git commit -m ");
+    }
+
+    #[test]
+    fn menu_completion() {
+        let mut context = Context::new();
+        context.completer = Some(Box::new(BasicCompleter::new(vec!["bar", "baz", "qux"])));
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        ed.insert_str_after_cursor("x b").unwrap();
+
+        ed.menu_complete(&mut |_| {}, false).unwrap();
+        let expected = [Completion::new("bar"), Completion::new("baz")];
+        assert_eq!(ed.menu_completions(), Some((&expected[..], 0)));
+        assert_eq!(String::from(ed.current_buffer().clone()), "x bar");
+
+        ed.menu_complete(&mut |_| {}, false).unwrap();
+        assert_eq!(String::from(ed.current_buffer().clone()), "x baz");
+        ed.move_menu_selection(MenuMove::Next).unwrap();
+        assert_eq!(String::from(ed.current_buffer().clone()), "x bar");
+        assert_eq!(ed.cursor(), 5);
+
+        ed.cancel_menu_completion().unwrap();
+        assert!(!ed.is_menu_completing());
+        assert_eq!(String::from(ed.current_buffer().clone()), "x b");
+
+        ed.menu_complete(&mut |_| {}, true).unwrap();
+        ed.accept_menu_completion().unwrap();
+        assert_eq!(String::from(ed.current_buffer().clone()), "x baz");
+        ed.undo().unwrap();
+        assert_eq!(String::from(ed), "x b");
+    }
+
+    #[test]
+    fn menu_grid() {
+        let mut context = Context::new();
+        let words: Vec<String> = (0..300).map(|i| format!("word{:03}", i)).collect();
+        context.completer = Some(Box::new(BasicCompleter::new(words)));
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+
+        // 8 columns fit in 80, and 22 rows fit below the buffer, with one more to show the page
+        ed.menu_complete(&mut |_| {}, false).unwrap();
+        ed.move_menu_selection(MenuMove::Down).unwrap();
+        assert_eq!(ed.menu_completions().unwrap().1, 8);
+        ed.move_menu_selection(MenuMove::Up).unwrap();
+        ed.move_menu_selection(MenuMove::Up).unwrap();
+        assert_eq!(ed.menu_completions().unwrap().1, 296);
+        ed.move_menu_selection(MenuMove::Down).unwrap();
+        assert_eq!(ed.menu_completions().unwrap().1, 0);
+
+        ed.out.clear();
+        ed.move_menu_selection(MenuMove::NextPage).unwrap();
+        assert_eq!(ed.menu_completions().unwrap().1, 176);
+        let output = String::from_utf8(ed.out.clone()).unwrap();
+        assert!(output.contains(&format!("{}word176  {}word177", style::Invert, style::NoInvert)));
+        assert!(output.contains("177-300 of 300"));
+        assert!(!output.contains("word175"));
+    }
+
+    struct FlagCompleter;
+
+    impl Completer for FlagCompleter {
+        fn rich_completions(&self, start: &str) -> Vec<Completion> {
+            vec![
+                Completion::new("--verbose").with_description("say more").with_suffix(" "),
+                Completion::new("--quiet").with_description("say less").with_suffix(" "),
+                Completion::new("-q").with_display("-q (short)").with_suffix(" "),
+            ].into_iter()
+                .filter(|c| c.text.starts_with(start))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn completion_suffix() {
+        let mut context = Context::new();
+        context.completer = Some(Box::new(FlagCompleter));
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+
+        ed.insert_str_after_cursor("ls --v").unwrap();
+        ed.complete(&mut |_| {}).unwrap();
+        assert_eq!(String::from(ed.current_buffer().clone()), "ls --verbose ");
+
+        ed.insert_str_after_cursor("--").unwrap();
+        ed.menu_complete(&mut |_| {}, false).unwrap();
+        assert_eq!(String::from(ed.current_buffer().clone()), "ls --verbose --quiet");
+        ed.cancel_menu_completion().unwrap();
+        assert_eq!(String::from(ed.current_buffer().clone()), "ls --verbose --");
+
+        ed.menu_complete(&mut |_| {}, false).unwrap();
+        ed.accept_menu_completion().unwrap();
+        assert_eq!(String::from(ed), "ls --verbose --quiet ");
+    }
+
+    #[test]
+    fn completion_descriptions() {
+        let mut context = Context::new();
+        context.completer = Some(Box::new(FlagCompleter));
+        context.completion_options.show_all_if_ambiguous = true;
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+
+        ed.insert_str_after_cursor("-").unwrap();
+        ed.out.clear();
+        ed.complete(&mut |_| {}).unwrap();
+        let output = String::from_utf8(ed.out.clone()).unwrap();
+        assert!(output.contains("--quiet     say less\r\n--verbose   say more\r\n-q (short)"));
+    }
+
+    /// Completes `$` variables, replacing the whole variable even past the cursor, and commands.
+    struct VariableCompleter;
+
+    impl Completer for VariableCompleter {
+        fn rich_completions(&self, _: &str) -> Vec<Completion> {
+            Vec::new()
+        }
+
+        fn complete_line(&self, line: &CompletionContext) -> LineCompletions {
+            let text = String::from(line.buffer.clone());
+            let start = text[..line.cursor].rfind('$').unwrap_or(line.cursor);
+            let end = text[start..].find(' ').map_or(text.len(), |i| start + i);
+            let completions = if start < line.cursor {
+                vec!["$HOME", "$HOSTNAME"]
+                    .into_iter()
+                    .filter(|v| v.starts_with(&text[start..line.cursor]))
+                    .map(Completion::new)
+                    .collect()
+            } else if line.word_index() == 0 {
+                vec![Completion::new("echo").with_suffix(" ")]
+            } else {
+                vec![]
+            };
+            LineCompletions {
+                start: start,
+                end: end,
+                completions: completions,
+                hint: None,
+            }
+        }
+    }
+
+    #[test]
+    fn complete_line() {
+        let mut context = Context::new();
+        context.completer = Some(Box::new(VariableCompleter));
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+
+        ed.complete(&mut |_| {}).unwrap();
+        ed.insert_str_after_cursor("$HOSx x").unwrap();
+        ed.move_cursor_to(9).unwrap();
+        ed.complete(&mut |_| {}).unwrap();
+        assert_eq!(String::from(ed.current_buffer().clone()), "echo $HOSTNAME x");
+        assert_eq!(ed.cursor(), 14);
+
+        ed.move_cursor_to_end_of_line().unwrap();
+        ed.insert_str_after_cursor(" $H").unwrap();
+        ed.menu_complete(&mut |_| {}, false).unwrap();
+        assert_eq!(String::from(ed.current_buffer().clone()), "echo $HOSTNAME x $HOME");
+        ed.cancel_menu_completion().unwrap();
+        assert_eq!(String::from(ed.current_buffer().clone()), "echo $HOSTNAME x $H");
+    }
+
+    #[test]
+    fn ranked_completions() {
+        let mut context = Context::new();
+        let completer = BasicCompleter::new(vec!["fooby", "foo_bar", "xfxb", "bar"])
+            .with_match_strategy(MatchStrategy::Fuzzy);
+        context.completer = Some(Box::new(completer));
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+
+        ed.insert_str_after_cursor("fb").unwrap();
+        ed.menu_complete(&mut |_| {}, false).unwrap();
+        assert_eq!(
+            ed.menu_completions().unwrap().0.iter().map(|c| &c.text[..]).collect::<Vec<_>>(),
+            vec!["foo_bar", "fooby", "xfxb"]
+        );
+        assert_eq!(String::from(ed.current_buffer().clone()), "foo_bar");
+        ed.cancel_menu_completion().unwrap();
+
+        ed.insert_str_after_cursor(" xx").unwrap();
+        ed.complete(&mut |_| {}).unwrap();
+        assert_eq!(String::from(ed), "fb xfxb");
+    }
+
+    #[test]
+    fn ignore_case_completion() {
+        let mut context = Context::new();
+        context.completer = Some(Box::new(BasicCompleter::new(vec!["Makefile", "main.rs"])));
+        context.completion_options.ignore_case = true;
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+
+        ed.insert_str_after_cursor("mak").unwrap();
+        ed.complete(&mut |_| {}).unwrap();
+        assert_eq!(String::from(ed), "Makefile");
+    }
+
+    /// Completes `start` with `"rc"`, counting how many times it is run. It holds an `Rc`, so it
+    /// can't run on another thread.
+    struct RcCompleter(Rc<Cell<usize>>);
+
+    impl Completer for RcCompleter {
+        fn completions(&self, start: &str) -> Vec<String> {
+            self.0.set(self.0.get() + 1);
+            vec![format!("{}rc", start)]
+        }
+    }
+
+    #[test]
+    fn async_completer_replaces_completer() {
+        let runs = Rc::new(Cell::new(0));
+        let mut context = Context::new();
+        context.completer = Some(Box::new(RcCompleter(runs.clone())));
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+
+        ed.insert_str_after_cursor("a").unwrap();
+        ed.complete(&mut |_| {}).unwrap();
+        assert_eq!(String::from(ed.current_buffer().clone()), "arc");
+        assert_eq!(runs.get(), 1);
+
+        // the async completer is used even when completion isn't asynchronous
+        ed.context.async_completer = Some(Arc::new(BasicCompleter::new(vec!["arcade"])));
+        ed.complete(&mut |_| {}).unwrap();
+        assert_eq!(String::from(ed.current_buffer().clone()), "arcade");
+        assert_eq!(runs.get(), 1);
+    }
+
+    /// Completes `start` with `"slow"` after sleeping.
+    struct SlowCompleter(u64);
+
+    impl Completer for SlowCompleter {
+        fn completions(&self, start: &str) -> Vec<String> {
+            thread::sleep(Duration::from_millis(self.0));
+            vec![format!("{}slow", start)]
+        }
+    }
+
+    #[test]
+    fn async_completion() {
+        let mut context = Context::new();
+        context.async_completer = Some(Arc::new(SlowCompleter(250)));
+        context.completion_options.asynchronous = true;
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        ed.key_pressed = || false;
+
+        ed.insert_str_after_cursor("a").unwrap();
+        ed.out.clear();
+        ed.complete(&mut |_| {}).unwrap();
+        assert_eq!(String::from(ed.current_buffer().clone()), "aslow");
+
+        let output = String::from_utf8(ed.out.clone()).unwrap();
+        assert!(output.contains("\r\n| completing..."));
+        assert!(output.contains("\r\n/ completing..."));
+        assert!(ed.spinner.is_none());
+    }
+
+    #[test]
+    fn async_completion_cancelled() {
+        let mut context = Context::new();
+        context.async_completer = Some(Arc::new(SlowCompleter(1000)));
+        context.completion_options.asynchronous = true;
+        context.completion_options.timeout = Some(Duration::from_millis(50));
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        ed.key_pressed = || false;
+
+        ed.insert_str_after_cursor("a").unwrap();
+        let started = Instant::now();
+        ed.complete(&mut |_| {}).unwrap();
+        assert!(started.elapsed() < Duration::from_millis(1000));
+        assert_eq!(String::from(ed.current_buffer().clone()), "a");
+
+        ed.context.completion_options.timeout = None;
+        ed.key_pressed = || true;
+        ed.menu_complete(&mut |_| {}, false).unwrap();
+        assert!(started.elapsed() < Duration::from_millis(1000));
+        assert_eq!(String::from(ed), "a");
+    }
+
+    /// Completes `start` with `"slow"` after sleeping, counting how many times it is run.
+    struct CountingCompleter(AtomicUsize);
+
+    impl Completer for CountingCompleter {
+        fn completions(&self, start: &str) -> Vec<String> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(200));
+            vec![format!("{}slow", start)]
+        }
+    }
+
+    #[test]
+    fn async_completion_single_thread() {
+        let completer = Arc::new(CountingCompleter(AtomicUsize::new(0)));
+        let mut context = Context::new();
+        context.async_completer = Some(completer.clone());
+        context.completion_options.asynchronous = true;
+        context.completion_options.timeout = Some(Duration::from_millis(20));
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        ed.key_pressed = || false;
+
+        // cancelling twice leaves the first thread running, and reuses it
+        ed.insert_str_after_cursor("a").unwrap();
+        ed.complete(&mut |_| {}).unwrap();
+        ed.complete(&mut |_| {}).unwrap();
+        assert!(ed.context.background_completion.is_some());
+        assert_eq!(completer.0.load(Ordering::SeqCst), 1);
+
+        // another line waits for the running completion before starting its own
+        ed.context.completion_options.timeout = None;
+        ed.insert_str_after_cursor("b").unwrap();
+        ed.complete(&mut |_| {}).unwrap();
+        assert_eq!(String::from(ed.current_buffer().clone()), "abslow");
+        assert!(ed.context.background_completion.is_none());
+        assert_eq!(completer.0.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn completion_hint() {
+        let mut context = Context::new();
+        let spec = CommandSpec::new("echo").with_arg(ArgSpec::new("text", ValueSpec::Any));
+        context.completer = Some(Box::new(GrammarCompleter::new(vec![spec])));
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+
+        ed.insert_str_after_cursor("echo ").unwrap();
+        ed.out.clear();
+        ed.complete(&mut |_| {}).unwrap();
+        let output = String::from_utf8(ed.out.clone()).unwrap();
+        assert!(output.starts_with("\r\n<text>\r\n"));
+        assert_eq!(String::from(ed), "echo ");
+    }
 }
@@ -4,13 +4,13 @@ use Editor;
 
 pub type EventHandler<'a, W> = FnMut(Event<W>) + 'a;
 
-pub struct Event<'a, 'out: 'a, W: Write + 'a> {
+pub struct Event<'a, 'out: 'a, W: Write + ?Sized + 'a> {
     pub editor: &'a mut Editor<'out, W>,
-    pub kind: EventKind,
+    pub kind: EventKind<'a>,
 }
 
-impl<'a, 'out: 'a, W: Write + 'a> Event<'a, 'out, W> {
-    pub fn new(editor: &'a mut Editor<'out, W>, kind: EventKind) -> Self {
+impl<'a, 'out: 'a, W: Write + ?Sized + 'a> Event<'a, 'out, W> {
+    pub fn new(editor: &'a mut Editor<'out, W>, kind: EventKind<'a>) -> Self {
         Event {
             editor: editor,
             kind: kind,
@@ -19,11 +19,14 @@ impl<'a, 'out: 'a, W: Write + 'a> Event<'a, 'out, W> {
 }
 
 #[derive(Debug)]
-pub enum EventKind {
+pub enum EventKind<'a> {
     /// Sent before handling a keypress.
     BeforeKey(Key),
     /// Sent after handling a keypress.
     AfterKey(Key),
     /// Sent in `Editor.complete()`, before processing the completion.
     BeforeComplete,
+    /// Sent before inserting text pasted into a terminal which supports bracketed paste. The text
+    /// can be changed, for example to remove control characters.
+    Paste(&'a mut String),
 }
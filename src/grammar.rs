@@ -0,0 +1,558 @@
+use std::path::PathBuf;
+
+use Completer;
+use Completion;
+use CompletionContext;
+use FilenameCompleter;
+use LineCompletions;
+use MatchStrategy;
+
+/// The values taken by an argument or flag.
+pub enum ValueSpec {
+    /// Any value, which isn't completed. The argument's hint is shown instead.
+    Any,
+    /// A path to a file or directory.
+    File,
+    /// A path to a directory.
+    Dir,
+    /// One of these values.
+    OneOf(Vec<String>),
+    /// Values completed by a completer, with `Completer::rich_completions()`.
+    Custom(Box<Completer>),
+}
+
+/// An argument of a command, or the value of a flag.
+pub struct ArgSpec {
+    name: String,
+    description: Option<String>,
+    values: ValueSpec,
+    repeated: bool,
+}
+
+impl ArgSpec {
+    /// `name` is shown in the hint for the argument, such as `<name>`.
+    pub fn new<S: Into<String>>(name: S, values: ValueSpec) -> Self {
+        ArgSpec {
+            name: name.into(),
+            description: None,
+            values: values,
+            repeated: false,
+        }
+    }
+
+    pub fn with_description<S: Into<String>>(mut self, description: S) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Whether the argument can be given any number of times. Only the last argument of a command
+    /// can be repeated.
+    pub fn with_repeated(mut self, repeated: bool) -> Self {
+        self.repeated = repeated;
+        self
+    }
+
+    /// Describes the argument, for when there is nothing to complete.
+    fn hint(&self) -> String {
+        match self.description {
+            Some(ref description) => format!("<{}>  {}", self.name, description),
+            None => format!("<{}>", self.name),
+        }
+    }
+}
+
+/// A flag of a command, such as `--verbose` or `-v`.
+pub struct FlagSpec {
+    long: Option<String>,
+    short: Option<char>,
+    description: Option<String>,
+    value: Option<ArgSpec>,
+}
+
+impl FlagSpec {
+    /// A flag with a long name, given without the dashes. A short name can be added with
+    /// `with_short()`.
+    pub fn long<S: Into<String>>(name: S) -> Self {
+        FlagSpec {
+            long: Some(name.into()),
+            short: None,
+            description: None,
+            value: None,
+        }
+    }
+
+    /// A flag with only a short name, given without the dash.
+    pub fn short_only(name: char) -> Self {
+        FlagSpec {
+            long: None,
+            short: Some(name),
+            description: None,
+            value: None,
+        }
+    }
+
+    pub fn with_short(mut self, name: char) -> Self {
+        self.short = Some(name);
+        self
+    }
+
+    pub fn with_description<S: Into<String>>(mut self, description: S) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// The flag takes a value, given as the next word, or after `=` for long flags, or after
+    /// the short flag in the same word.
+    pub fn with_value(mut self, value: ArgSpec) -> Self {
+        self.value = Some(value);
+        self
+    }
+
+    fn has_long(&self, name: &str) -> bool {
+        self.long.as_ref().map_or(false, |long| long == name)
+    }
+}
+
+/// A command, with its subcommands, flags and arguments.
+///
+/// ```
+/// use liner::*;
+///
+/// let git = CommandSpec::new("git")
+///     .with_subcommand(
+///         CommandSpec::new("commit")
+///             .with_description("Record changes")
+///             .with_flag(FlagSpec::long("all").with_short('a'))
+///             .with_flag(
+///                 FlagSpec::long("message")
+///                     .with_short('m')
+///                     .with_value(ArgSpec::new("msg", ValueSpec::Any)),
+///             ),
+///     )
+///     .with_subcommand(
+///         CommandSpec::new("add")
+///             .with_arg(ArgSpec::new("path", ValueSpec::File).with_repeated(true)),
+///     );
+/// let completer = GrammarCompleter::new(vec![git]);
+/// ```
+pub struct CommandSpec {
+    name: String,
+    description: Option<String>,
+    subcommands: Vec<CommandSpec>,
+    flags: Vec<FlagSpec>,
+    args: Vec<ArgSpec>,
+}
+
+impl CommandSpec {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        CommandSpec {
+            name: name.into(),
+            description: None,
+            subcommands: Vec::new(),
+            flags: Vec::new(),
+            args: Vec::new(),
+        }
+    }
+
+    pub fn with_description<S: Into<String>>(mut self, description: S) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Adds a subcommand, which can be given before the command's arguments.
+    pub fn with_subcommand(mut self, subcommand: CommandSpec) -> Self {
+        self.subcommands.push(subcommand);
+        self
+    }
+
+    /// Adds a flag. Subcommands accept the flags of the commands they belong to as well.
+    pub fn with_flag(mut self, flag: FlagSpec) -> Self {
+        self.flags.push(flag);
+        self
+    }
+
+    /// Adds the next positional argument.
+    pub fn with_arg(mut self, arg: ArgSpec) -> Self {
+        self.args.push(arg);
+        self
+    }
+
+    fn completion(&self) -> Completion {
+        let completion = Completion::new(&self.name[..]).with_suffix(" ");
+        match self.description {
+            Some(ref description) => completion.with_description(&description[..]),
+            None => completion,
+        }
+    }
+
+    /// The positional argument with index `i`.
+    fn arg_at(&self, i: usize) -> Option<&ArgSpec> {
+        match self.args.last() {
+            Some(last) if i >= self.args.len() && last.repeated => Some(last),
+            _ => self.args.get(i),
+        }
+    }
+}
+
+/// What the word being completed is, found by reading the words before it.
+enum Expected<'a> {
+    CommandName,
+    FlagValue(&'a ArgSpec),
+    /// A subcommand, flag or argument of the first of the commands, which are the subcommands
+    /// given so far, innermost first.
+    Arg {
+        commands: Vec<&'a CommandSpec>,
+        index: usize,
+        flags_done: bool,
+    },
+    /// The command is unknown.
+    Nothing,
+}
+
+/// Completes command lines from a description of the commands: their subcommands, flags and
+/// arguments, and the values they take. When there is nothing to complete, a hint describing
+/// the expected argument is shown.
+pub struct GrammarCompleter {
+    commands: Vec<CommandSpec>,
+    working_dir: Option<PathBuf>,
+    strategy: MatchStrategy,
+}
+
+impl GrammarCompleter {
+    /// `commands` are the commands which can be given as the first word.
+    pub fn new(commands: Vec<CommandSpec>) -> Self {
+        GrammarCompleter {
+            commands: commands,
+            working_dir: None,
+            strategy: MatchStrategy::Prefix,
+        }
+    }
+
+    /// Sets the directory which file and directory values are completed from, instead of the
+    /// current directory.
+    pub fn with_working_dir<T: Into<PathBuf>>(mut self, working_dir: T) -> Self {
+        self.working_dir = Some(working_dir.into());
+        self
+    }
+
+    /// Sets how command names, flags and `ValueSpec::OneOf` values are matched. The default is
+    /// `MatchStrategy::Prefix`.
+    pub fn with_match_strategy(mut self, strategy: MatchStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Reads the words before the word being completed.
+    fn expected<'a>(&'a self, line: &CompletionContext) -> Expected<'a> {
+        if line.word_index() == 0 {
+            return Expected::CommandName;
+        }
+
+        let words = (1..line.word_index()).filter_map(|i| line.nth_word(i));
+        let name = line.nth_word(0).unwrap_or_default();
+        let mut commands = match self.commands.iter().find(|c| c.name == name) {
+            Some(command) => vec![command],
+            None => return Expected::Nothing,
+        };
+        let mut index = 0;
+        let mut flags_done = false;
+        let mut flag_value = None;
+
+        for word in words {
+            if flag_value.take().is_some() {
+                // the word is the flag's value
+                continue;
+            }
+
+            if flags_done || word == "-" || !word.starts_with('-') {
+                let subcommand = commands[0].subcommands.iter().find(|c| c.name == word);
+                match subcommand {
+                    Some(subcommand) if index == 0 => commands.insert(0, subcommand),
+                    _ => index += 1,
+                }
+            } else if word == "--" {
+                flags_done = true;
+            } else if word.starts_with("--") {
+                if !word.contains('=') {
+                    flag_value = find_flag(&commands, |f| f.has_long(&word[2..]));
+                }
+            } else {
+                // The first short flag which takes a value takes the rest of the word, or the
+                // next word if it is last.
+                let flags: Vec<char> = word[1..].chars().collect();
+                for (i, &c) in flags.iter().enumerate() {
+                    if let Some(value) = find_flag(&commands, |f| f.short == Some(c)) {
+                        if i == flags.len() - 1 {
+                            flag_value = Some(value);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+
+        match flag_value {
+            Some(value) => Expected::FlagValue(value),
+            None => Expected::Arg {
+                commands: commands,
+                index: index,
+                flags_done: flags_done,
+            },
+        }
+    }
+
+    /// Completes a value of `arg`, or gives its hint if there is nothing to complete.
+    fn complete_arg(
+        &self,
+        arg: &ArgSpec,
+        word: &str,
+        line: &CompletionContext,
+        res: &mut LineCompletions,
+    ) {
+        let file_strategy = line.match_strategy(MatchStrategy::Prefix);
+        let completions = match arg.values {
+            ValueSpec::Any => Vec::new(),
+            ValueSpec::File => FilenameCompleter::new(self.working_dir.as_ref())
+                .with_match_strategy(file_strategy)
+                .rich_completions(word),
+            ValueSpec::Dir => FilenameCompleter::new(self.working_dir.as_ref())
+                .with_dirs_only(true)
+                .with_match_strategy(file_strategy)
+                .rich_completions(word),
+            ValueSpec::OneOf(ref values) => {
+                let completions = values
+                    .iter()
+                    .map(|v| Completion::new(&v[..]).with_suffix(" "))
+                    .collect();
+                line.match_strategy(self.strategy).filter(word, completions)
+            }
+            ValueSpec::Custom(ref completer) => completer.rich_completions(word),
+        };
+
+        res.completions.extend(completions);
+        if res.completions.is_empty() {
+            res.hint = Some(arg.hint());
+        }
+    }
+
+    fn complete_flags(
+        &self,
+        commands: &[&CommandSpec],
+        word: &str,
+        strategy: MatchStrategy,
+    ) -> Vec<Completion> {
+        let flags = commands.iter().flat_map(|c| c.flags.iter());
+        let mut completions = Vec::new();
+        for flag in flags {
+            let names = flag.long
+                .iter()
+                .map(|l| format!("--{}", l))
+                .chain(flag.short.iter().map(|s| format!("-{}", s)));
+            for name in names {
+                let completion = Completion::new(name).with_suffix(" ");
+                completions.push(match flag.description {
+                    Some(ref description) => completion.with_description(&description[..]),
+                    None => completion,
+                });
+            }
+        }
+        strategy.filter(word, completions)
+    }
+}
+
+/// The flag of any of the commands which matches, with the value it takes, if any.
+fn find_flag<'a, F>(commands: &[&'a CommandSpec], matches: F) -> Option<&'a ArgSpec>
+where
+    F: Fn(&FlagSpec) -> bool,
+{
+    commands
+        .iter()
+        .flat_map(|c| c.flags.iter())
+        .find(|f| matches(f))
+        .and_then(|f| f.value.as_ref())
+}
+
+impl Completer for GrammarCompleter {
+    /// Completes `start` as the name of a command, since there is no line to parse.
+    fn rich_completions(&self, start: &str) -> Vec<Completion> {
+        let commands = self.commands.iter().map(|c| c.completion()).collect();
+        self.strategy.filter(start, commands)
+    }
+
+    fn complete_line(&self, line: &CompletionContext) -> LineCompletions {
+        let word = line.word();
+        let strategy = line.match_strategy(self.strategy);
+        let mut res = LineCompletions {
+            start: line.word_start(),
+            end: line.cursor,
+            ..LineCompletions::default()
+        };
+
+        match self.expected(line) {
+            Expected::CommandName => {
+                let commands = self.commands.iter().map(|c| c.completion()).collect();
+                res.completions = strategy.filter(&word, commands);
+            }
+            Expected::FlagValue(arg) => self.complete_arg(arg, &word, line, &mut res),
+            Expected::Arg { ref commands, .. } if word.starts_with("--") && word.contains('=') => {
+                // the value of a long flag, after the `=`
+                let i = word.find('=').unwrap();
+                let flag = find_flag(commands, |f| f.has_long(&word[2..i]));
+                if let Some(arg) = flag {
+                    res.start += word[..i + 1].chars().count();
+                    self.complete_arg(arg, &word[i + 1..], line, &mut res);
+                }
+            }
+            Expected::Arg { ref commands, flags_done: false, .. } if word.starts_with('-') => {
+                res.completions = self.complete_flags(commands, &word, strategy);
+            }
+            Expected::Arg { commands, index, .. } => {
+                if index == 0 {
+                    let subcommands = &commands[0].subcommands;
+                    let completions = subcommands.iter().map(|c| c.completion()).collect();
+                    res.completions = strategy.filter(&word, completions);
+                }
+                if let Some(arg) = commands[0].arg_at(index) {
+                    self.complete_arg(arg, &word, line, &mut res);
+                }
+            }
+            Expected::Nothing => {}
+        }
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use context::get_buffer_words;
+    use Buffer;
+    use CursorPosition;
+
+    fn git() -> CommandSpec {
+        CommandSpec::new("git")
+            .with_flag(FlagSpec::long("verbose").with_short('v'))
+            .with_subcommand(
+                CommandSpec::new("commit")
+                    .with_description("Record changes")
+                    .with_flag(
+                        FlagSpec::long("all")
+                            .with_short('a')
+                            .with_description("Commit all changes"),
+                    )
+                    .with_flag(
+                        FlagSpec::long("message").with_short('m').with_value(
+                            ArgSpec::new("msg", ValueSpec::Any)
+                                .with_description("the commit message"),
+                        ),
+                    ),
+            )
+            .with_subcommand(
+                CommandSpec::new("checkout").with_arg(ArgSpec::new(
+                    "branch",
+                    ValueSpec::OneOf(vec!["main".into(), "dev".into()]),
+                )),
+            )
+            .with_subcommand(
+                CommandSpec::new("add")
+                    .with_arg(ArgSpec::new("path", ValueSpec::File).with_repeated(true)),
+            )
+            .with_subcommand(CommandSpec::new("log").with_flag(FlagSpec::long("format").with_value(
+                ArgSpec::new("format", ValueSpec::OneOf(vec!["oneline".into(), "full".into()])),
+            )))
+    }
+
+    fn complete(completer: &GrammarCompleter, text: &str) -> LineCompletions {
+        let buf = Buffer::from(text);
+        let words = get_buffer_words(&buf);
+        let position = CursorPosition::get(buf.num_chars(), &words);
+        completer.complete_line(&CompletionContext::new(&buf, buf.num_chars(), &words, position))
+    }
+
+    fn texts(completer: &GrammarCompleter, text: &str) -> Vec<String> {
+        complete(completer, text)
+            .completions
+            .into_iter()
+            .map(|c| c.text)
+            .collect()
+    }
+
+    #[test]
+    fn subcommands() {
+        let completer = GrammarCompleter::new(vec![git()]);
+
+        assert_eq!(texts(&completer, "g"), vec!["git"]);
+        assert_eq!(completer.completions("g"), vec!["git"]);
+        assert_eq!(texts(&completer, "hg "), Vec::<String>::new());
+        assert_eq!(texts(&completer, "git "), vec!["add", "checkout", "commit", "log"]);
+        assert_eq!(texts(&completer, "git c"), vec!["checkout", "commit"]);
+
+        let res = complete(&completer, "git co");
+        assert_eq!((res.start, res.end), (4, 6));
+        assert_eq!(res.completions[0].description, Some("Record changes".to_owned()));
+        assert_eq!(res.completions[0].suffix, " ");
+    }
+
+    #[test]
+    fn ignore_case() {
+        let completer = GrammarCompleter::new(vec![git()]);
+        assert_eq!(texts(&completer, "git CH"), Vec::<String>::new());
+
+        let buf = Buffer::from("git CH");
+        let words = get_buffer_words(&buf);
+        let position = CursorPosition::get(buf.num_chars(), &words);
+        let line = CompletionContext::new(&buf, buf.num_chars(), &words, position)
+            .with_ignore_case(true);
+        let res = completer.complete_line(&line);
+        assert_eq!(res.completions.len(), 1);
+        assert_eq!(res.completions[0].text, "checkout");
+    }
+
+    #[test]
+    fn flags() {
+        let completer = GrammarCompleter::new(vec![git()]);
+
+        assert_eq!(
+            texts(&completer, "git commit -"),
+            vec!["--all", "--message", "--verbose", "-a", "-m", "-v"]
+        );
+        assert_eq!(texts(&completer, "git -"), vec!["--verbose", "-v"]);
+        assert_eq!(texts(&completer, "git -v checkout m"), vec!["main"]);
+        assert_eq!(texts(&completer, "git commit -m msg "), Vec::<String>::new());
+        assert_eq!(texts(&completer, "git commit -mmsg -"), texts(&completer, "git commit -"));
+
+        let res = complete(&completer, "git log --format=o");
+        assert_eq!((res.start, res.end), (17, 18));
+        assert_eq!(res.completions[0].text, "oneline");
+        assert_eq!(texts(&completer, "git log --format "), vec!["full", "oneline"]);
+    }
+
+    #[test]
+    fn args() {
+        let dir = env::temp_dir().join("liner_test_grammar");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::File::create(dir.join("-x")).unwrap();
+        fs::File::create(dir.join("a.txt")).unwrap();
+        let completer = GrammarCompleter::new(vec![git()]).with_working_dir(&dir);
+
+        assert_eq!(texts(&completer, "git checkout "), vec!["dev", "main"]);
+        assert_eq!(texts(&completer, "git checkout main "), Vec::<String>::new());
+        assert_eq!(texts(&completer, "git add "), vec!["-x", "a.txt"]);
+        assert_eq!(texts(&completer, "git add a.txt a"), vec!["a.txt"]);
+        assert_eq!(texts(&completer, "git add -"), vec!["--verbose", "-v"]);
+        assert_eq!(texts(&completer, "git add -- -"), vec!["-x"]);
+
+        let res = complete(&completer, "git commit -m ");
+        assert!(res.completions.is_empty());
+        assert_eq!(res.hint, Some("<msg>  the commit message".to_owned()));
+        assert_eq!(complete(&completer, "git commit -am ").hint, res.hint);
+        assert_eq!(complete(&completer, "git commit --message=").hint, res.hint);
+        assert_eq!(complete(&completer, "git add b").hint, Some("<path>".to_owned()));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+use termion::style;
+
+/// A part of the buffer to style, for `Highlighter::spans()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    /// Byte offset of the start of the span.
+    pub start: usize,
+    /// Byte offset of the end of the span, exclusive.
+    pub end: usize,
+    /// The ANSI codes to write before the span, for example
+    /// `termion::color::Fg(termion::color::Red).to_string()`. The style is reset after the span.
+    pub style: String,
+}
+
+impl Span {
+    pub fn new<S: ToString>(start: usize, end: usize, style: S) -> Self {
+        Span {
+            start: start,
+            end: end,
+            style: style.to_string(),
+        }
+    }
+}
+
+/// Colors the buffer as it is displayed, for example to show commands, strings and errors in a
+/// shell. Implement either `spans()` or `highlight()`.
+///
+/// The highlighted text must take up as much space on the terminal as the buffer text, line by
+/// line, or it is not used. Only ANSI escape codes can be added.
+///
+/// ```
+/// extern crate liner;
+/// extern crate termion;
+///
+/// use liner::*;
+/// use termion::color;
+///
+/// struct Numbers;
+///
+/// impl Highlighter for Numbers {
+///     fn spans(&self, text: &str, _cursor: usize) -> Vec<Span> {
+///         text.char_indices()
+///             .filter(|&(_, c)| c.is_digit(10))
+///             .map(|(i, _)| Span::new(i, i + 1, color::Fg(color::Blue)))
+///             .collect()
+///     }
+/// }
+///
+/// # fn main() {
+/// let mut context = Context::new();
+/// context.highlighter = Some(Box::new(Numbers));
+/// # }
+/// ```
+pub trait Highlighter {
+    /// Returns the parts of `text` to style, in order and without overlapping. `cursor` is the
+    /// position of the cursor, in characters.
+    fn spans(&self, text: &str, cursor: usize) -> Vec<Span> {
+        let _ = (text, cursor);
+        Vec::new()
+    }
+
+    /// Returns `text` with ANSI escape codes added. By default, this styles the `spans()`.
+    fn highlight(&self, text: &str, cursor: usize) -> String {
+        render_spans(text, &self.spans(text, cursor))
+    }
+}
+
+impl<F: Fn(&str, usize) -> String> Highlighter for F {
+    fn highlight(&self, text: &str, cursor: usize) -> String {
+        self(text, cursor)
+    }
+}
+
+/// Writes the style of each span before it, and resets the style after it. Spans which overlap
+/// an earlier span, or are out of bounds, are skipped.
+pub fn render_spans(text: &str, spans: &[Span]) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut pos = 0;
+
+    for span in spans {
+        if span.start < pos || span.end < span.start || span.end > text.len() ||
+            !text.is_char_boundary(span.start) || !text.is_char_boundary(span.end)
+        {
+            continue;
+        }
+
+        res.push_str(&text[pos..span.start]);
+        res.push_str(&span.style);
+        res.push_str(&text[span.start..span.end]);
+        res.push_str(&style::Reset.to_string());
+        pos = span.end;
+    }
+
+    res.push_str(&text[pos..]);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use termion::color;
+
+    #[test]
+    fn render() {
+        let spans = vec![
+            Span::new(0, 2, color::Fg(color::Red)),
+            Span::new(1, 3, style::Bold),
+            Span::new(3, 4, style::Bold),
+            Span::new(5, 9, style::Bold),
+        ];
+        assert_eq!(
+            render_spans("ab cd", &spans),
+            format!("{}ab{} {}c{}d", color::Fg(color::Red), style::Reset, style::Bold, style::Reset)
+        );
+    }
+
+    #[test]
+    fn closure_highlighter() {
+        let highlighter = |text: &str, _| text.to_uppercase();
+        assert_eq!(highlighter.highlight("abc", 0), "ABC");
+        assert_eq!(highlighter.spans("abc", 0), vec![]);
+    }
+}
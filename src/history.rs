@@ -1,29 +1,201 @@
 use super::*;
 
 use std::collections::{vec_deque, VecDeque};
-use std::io::{BufRead, BufReader, Error, ErrorKind};
-use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind};
+use std::fs::{self, File, OpenOptions, Permissions};
 use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::iter::IntoIterator;
 use std::ops::Index;
 use std::ops::IndexMut;
+use std::process;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc::{channel, Sender};
 use std::thread::{sleep, spawn, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use bytecount::count;
 
 const DEFAULT_MAX_SIZE: usize = 1000;
 
+/// Marks a line of the history file written in the `Extended` format.
+const EXTENDED_PREFIX: &str = "#liner\t";
+
+/// How entries are written to the history file. Files in either format, or a mix of both, can be
+/// loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryFileFormat {
+    /// One line per entry, containing only the text of the entry. Entries which span several
+    /// lines are written in the `Extended` format, without their metadata, so that they are read
+    /// back as one entry.
+    Plain,
+    /// One line per entry, containing the text of the entry and its `HistoryMetadata`.
+    Extended,
+}
+
+impl Default for HistoryFileFormat {
+    fn default() -> Self {
+        HistoryFileFormat::Plain
+    }
+}
+
+/// Information about a history entry, stored with it in the `Extended` history file format.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryMetadata {
+    /// When the entry was added, in seconds since the Unix epoch.
+    pub timestamp: Option<u64>,
+    /// The working directory the command was run in.
+    pub cwd: Option<String>,
+    /// The exit status of the command.
+    pub exit_status: Option<i32>,
+    /// Identifies the shell session the command was run in.
+    pub session_id: Option<String>,
+    /// How long the command took to run.
+    pub duration: Option<Duration>,
+}
+
+impl HistoryMetadata {
+    /// Writes the entry `text` with this metadata as a line of the `Extended` format, without
+    /// the trailing newline.
+    fn to_line(&self, text: &str) -> String {
+        let mut line = String::from(EXTENDED_PREFIX);
+        if let Some(timestamp) = self.timestamp {
+            line.push_str(&format!("when={}\t", timestamp));
+        }
+        if let Some(ref cwd) = self.cwd {
+            line.push_str(&format!("cwd={}\t", escape(cwd)));
+        }
+        if let Some(status) = self.exit_status {
+            line.push_str(&format!("exit={}\t", status));
+        }
+        if let Some(ref session_id) = self.session_id {
+            line.push_str(&format!("session={}\t", escape(session_id)));
+        }
+        if let Some(duration) = self.duration {
+            let millis = duration.as_secs() * 1000 + u64::from(duration.subsec_millis());
+            line.push_str(&format!("duration_ms={}\t", millis));
+        }
+        line.push_str("cmd=");
+        line.push_str(&escape(text));
+        line
+    }
+
+    /// Reads a line of the `Extended` format, returning the entry text and its metadata. Unknown
+    /// fields are ignored. Returns `None` if the line is not in the `Extended` format.
+    fn from_line(line: &str) -> Option<(String, HistoryMetadata)> {
+        if !line.starts_with(EXTENDED_PREFIX) {
+            return None;
+        }
+
+        let mut text = None;
+        let mut metadata = HistoryMetadata::default();
+        for field in line[EXTENDED_PREFIX.len()..].split('\t') {
+            let mut parts = field.splitn(2, '=');
+            let (key, value) = match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => (key, value),
+                _ => continue,
+            };
+            match key {
+                "cmd" => text = Some(unescape(value)),
+                "when" => metadata.timestamp = value.parse().ok(),
+                "cwd" => metadata.cwd = Some(unescape(value)),
+                "exit" => metadata.exit_status = value.parse().ok(),
+                "session" => metadata.session_id = Some(unescape(value)),
+                "duration_ms" => metadata.duration = value.parse().ok().map(Duration::from_millis),
+                _ => {}
+            }
+        }
+        text.map(|text| (text, metadata))
+    }
+}
+
+/// Whether `text` is read back as the same entry when written as a line of the `Plain` format.
+fn is_plain_line(text: &str) -> bool {
+    !text.contains('\n') && !text.contains('\r') && !text.starts_with(EXTENDED_PREFIX)
+}
+
+/// Escapes the characters which separate fields and lines in the `Extended` format.
+fn escape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => res.push_str("\\\\"),
+            '\t' => res.push_str("\\t"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+fn unescape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => res.push('\t'),
+            Some('n') => res.push('\n'),
+            Some('r') => res.push('\r'),
+            Some(c) => res.push(c),
+            None => res.push('\\'),
+        }
+    }
+    res
+}
+
+/// The direction in which to search through history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchDirection {
+    /// Search towards newer entries.
+    Forward,
+    /// Search towards older entries.
+    Backward,
+}
+
+/// How far a `History` has read the history file, to find the entries added by other sessions.
+///
+/// Other sessions only append to the file, or replace it by a new file without the oldest entries.
+/// So the new entries start at `offset`, unless the file was replaced.
+struct FilePosition {
+    /// Length of the file when it was last read or written.
+    offset: usize,
+    /// The `file_id()` of the file at that time.
+    file_id: Option<(u64, u64)>,
+}
+
+impl FilePosition {
+    fn new(offset: usize, metadata: &fs::Metadata) -> Self {
+        FilePosition {
+            offset: offset,
+            file_id: file_id(metadata),
+        }
+    }
+
+    /// Whether the entries after `offset` in the file with `metadata` are the ones added since
+    /// this position. If not, the file must be read again from the start.
+    fn is_before(&self, metadata: &fs::Metadata) -> bool {
+        self.file_id.is_some() &&
+            self.file_id == file_id(metadata) &&
+            self.offset as u64 <= metadata.len()
+    }
+}
+
 /// Structure encapsulating command history
 pub struct History {
     // TODO: this should eventually be private
     /// Vector of buffers to store history in
     pub buffers: VecDeque<Buffer>,
+    /// Metadata of the buffers, at the same indices
+    metadata: VecDeque<HistoryMetadata>,
     /// Store a filename to save history into; if None don't save history
     file_name: Option<String>,
+    /// Format of new entries written to the history file
+    file_format: HistoryFileFormat,
     /// Maximal number of buffers stored in the memory
     /// TODO: just make this public?
     max_size: usize,
@@ -35,10 +207,16 @@ pub struct History {
     /// Signals the background thread to stop when dropping the struct
     bg_stop: Arc<AtomicBool>,
     /// Sends commands to write to the history file
-    sender: Sender<(Buffer, String)>,
+    sender: Sender<(String, String)>,
+    /// How far the history file has been read or written, if it has been
+    file_position: Option<FilePosition>,
 
     // TODO set from environment variable?
     pub append_duplicate_entries: bool,
+    /// Share the history file with other sessions, like zsh's `SHARE_HISTORY`. New entries are
+    /// written to the file immediately, and `Context::read_line()` adds the entries written by
+    /// other sessions since the last prompt.
+    pub share_history: bool,
 }
 
 impl History {
@@ -63,7 +241,9 @@ impl History {
         let max_size = max_file_size.clone();
         History {
             buffers: VecDeque::with_capacity(DEFAULT_MAX_SIZE),
+            metadata: VecDeque::with_capacity(DEFAULT_MAX_SIZE),
             file_name: None,
+            file_format: HistoryFileFormat::default(),
             sender: sender,
             bg_handle: Some(spawn(move || {
                 while !stop_signal.load(Ordering::Relaxed) {
@@ -83,7 +263,9 @@ impl History {
             bg_stop: bg_stop,
             max_size: DEFAULT_MAX_SIZE,
             max_file_size: max_file_size,
+            file_position: None,
             append_duplicate_entries: false,
+            share_history: false,
         }
     }
 
@@ -96,23 +278,84 @@ impl History {
     /// size has been met. If writing to the disk is enabled, this function will be used for
     /// logging history to the designated history file.
     pub fn push(&mut self, new_item: Buffer) -> io::Result<()> {
-        self.file_name.as_ref().map(|name| {
-            let _ = self.sender.send((new_item.clone(), name.to_owned()));
-        });
+        self.push_with_metadata(new_item, HistoryMetadata::default())
+    }
 
+    /// Like `push()`, but also stores `metadata` with the command. If no timestamp is given, the
+    /// current time is used.
+    ///
+    /// The metadata is only written to the history file if its format is
+    /// `HistoryFileFormat::Extended`.
+    pub fn push_with_metadata(
+        &mut self,
+        new_item: Buffer,
+        mut metadata: HistoryMetadata,
+    ) -> io::Result<()> {
+        if metadata.timestamp.is_none() {
+            metadata.timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs());
+        }
+
+        let mut res = Ok(());
+        if let Some(name) = self.file_name.clone() {
+            let text = String::from(new_item.clone());
+            let line = match self.file_format {
+                HistoryFileFormat::Plain if is_plain_line(&text) => text,
+                HistoryFileFormat::Plain => HistoryMetadata::default().to_line(&text),
+                HistoryFileFormat::Extended => metadata.to_line(&text),
+            };
+            if self.share_history {
+                res = self.write_shared(&line, &name);
+            } else {
+                let _ = self.sender.send((line, name));
+            }
+        }
+
+        self.add_entry(new_item, metadata);
+        res
+    }
+
+    /// Adds an entry to the end of the history, unless it repeats the last entry and
+    /// `append_duplicate_entries` isn't set. Returns whether the entry was added.
+    fn add_entry(&mut self, new_item: Buffer, metadata: HistoryMetadata) -> bool {
         // buffers[0] is the oldest entry
         // the new entry goes to the end
         if !self.append_duplicate_entries
             && self.buffers.back().map(|b| b.to_string()) == Some(new_item.to_string())
         {
-            return Ok(());
+            return false;
         }
 
         self.buffers.push_back(new_item);
+        self.metadata.push_back(metadata);
+        self.truncate();
+        true
+    }
+
+    /// Writes `line` to the history file right away, first adding the entries other sessions
+    /// have written to it.
+    fn write_shared(&mut self, line: &str, file_name: &str) -> io::Result<()> {
+        let mut file = open_locked(file_name, &append_options(), LockMode::Exclusive)?;
+
+        self.read_new_entries(&mut file)?;
+        let max_file_size = self.max_file_size.load(Ordering::Relaxed);
+        let position = append_to_file(&mut file, file_name, max_file_size, line)?;
+        self.file_position = Some(position);
+        Ok(())
+    }
+
+    fn truncate(&mut self) {
         while self.buffers.len() > self.max_size {
             self.buffers.pop_front();
+            self.metadata.pop_front();
         }
-        Ok(())
+    }
+
+    /// Get the metadata of the entry at `index`, if there is such an entry.
+    pub fn metadata(&self, index: usize) -> Option<&HistoryMetadata> {
+        self.metadata.get(index)
     }
 
     /// Go through the history and try to find a buffer which starts the same as the new buffer
@@ -133,6 +376,29 @@ impl History {
         None
     }
 
+    /// Go through the history, starting at the entry with index `start` and moving in `direction`,
+    /// and find the first entry which contains `pattern`. Returns the index of that entry.
+    pub fn search(
+        &self,
+        start: usize,
+        direction: SearchDirection,
+        pattern: &[char],
+    ) -> Option<usize> {
+        let mut i = start;
+        while let Some(tested) = self.buffers.get(i) {
+            if tested.find(pattern).is_some() {
+                return Some(i);
+            }
+
+            i = match direction {
+                SearchDirection::Forward => i + 1,
+                SearchDirection::Backward if i > 0 => i - 1,
+                SearchDirection::Backward => break,
+            };
+        }
+        None
+    }
+
     /// Get the history file name.
     pub fn file_name(&self) -> Option<&str> {
         match self.file_name {
@@ -146,6 +412,17 @@ impl History {
         self.file_name = name;
     }
 
+    /// Get the format of entries written to the history file.
+    pub fn file_format(&self) -> HistoryFileFormat {
+        self.file_format
+    }
+
+    /// Set the format of entries written to the history file. Entries already in the file are
+    /// left as they are.
+    pub fn set_file_format(&mut self, format: HistoryFileFormat) {
+        self.file_format = format;
+    }
+
     /// Set maximal number of buffers stored in memory
     pub fn set_max_size(&mut self, size: usize) {
         self.max_size = size;
@@ -156,7 +433,7 @@ impl History {
         self.max_file_size.store(size, Ordering::Relaxed);
     }
 
-    /// Load history from given file name
+    /// Load history from given file name. Entries can be in either `HistoryFileFormat`.
     pub fn load_history(&mut self) -> io::Result<()> {
         let file_name = match self.file_name.clone() {
             Some(name) => name,
@@ -167,15 +444,73 @@ impl History {
                 ))
             }
         };
-        let file = try!(OpenOptions::new().read(true).open(file_name));
-        let reader = BufReader::new(file);
-        for line in reader.lines() {
-            match line {
-                Ok(line) => self.buffers.push_back(Buffer::from(line)),
-                Err(_) => break,
+        let mut file = try!(open_locked(
+            &file_name,
+            OpenOptions::new().read(true),
+            LockMode::Shared,
+        ));
+        self.file_position = None;
+        self.read_new_entries(&mut file).map(|_| ())
+    }
+
+    /// Add the entries which other sessions have written to the history file since it was last
+    /// read or written. Returns the number of entries added.
+    ///
+    /// If another session has replaced the file since, to remove the oldest entries from it, the
+    /// history is loaded again from the whole file instead, and entries which are no longer in the
+    /// file are dropped.
+    pub fn load_new_entries(&mut self) -> io::Result<usize> {
+        let file_name = match self.file_name.clone() {
+            Some(name) => name,
+            None => return Ok(0),
+        };
+        let mut file = match open_locked(
+            &file_name,
+            OpenOptions::new().read(true),
+            LockMode::Shared,
+        ) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        self.read_new_entries(&mut file)
+    }
+
+    /// Reads the entries after `file_position` from `file`, which must be locked.
+    fn read_new_entries(&mut self, file: &mut File) -> io::Result<usize> {
+        let file_metadata = file.metadata()?;
+        let start = match self.file_position {
+            Some(ref pos) if pos.is_before(&file_metadata) => pos.offset,
+            Some(_) => {
+                self.buffers.clear();
+                self.metadata.clear();
+                0
+            }
+            None => 0,
+        };
+
+        let mut contents = Vec::new();
+        file.seek(SeekFrom::Start(start as u64))?;
+        file.read_to_end(&mut contents)?;
+
+        let mut added = 0;
+        for line in contents.split(|&b| b == b'\n') {
+            let line = String::from_utf8_lossy(line);
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() {
+                continue;
+            }
+            let (text, metadata) = match HistoryMetadata::from_line(line) {
+                Some(entry) => entry,
+                None => (line.to_owned(), HistoryMetadata::default()),
+            };
+            if self.add_entry(Buffer::from(text), metadata) {
+                added += 1;
             }
         }
-        Ok(())
+
+        self.file_position = Some(FilePosition::new(start + contents.len(), &file_metadata));
+        Ok(added)
     }
 
     fn buffers_ref(&self) -> &VecDeque<Buffer> {
@@ -206,116 +541,145 @@ impl IndexMut<usize> for History {
     }
 }
 
-/// Perform write operation. If the history file does not exist, it will be created.
-/// This function is not part of the public interface.
-/// XXX: include more information in the file (like fish does)
-fn write_to_disk(max_file_size: usize, new_item: &Buffer, file_name: &str) -> io::Result<()> {
-    let mut file = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(file_name)?;
-
-    // The metadata contains the length of the file
-    let file_length = file.metadata().ok().map_or(0u64, |m| m.len());
-
-    {
-        // Count number of entries in file
-        let mut num_stored = 0;
-
-        // 4K byte buffer for reading chunks of the file at once.
-        let mut buffer = [0; 4096];
-
-        // Find the total number of commands in the file
-        loop {
-            // Read 4K of bytes all at once into the buffer.
-            let read = file.read(&mut buffer)?;
-            // If EOF is found, don't seek at all.
-            if read == 0 {
-                break;
-            }
-            // Count the number of commands that were found in the current buffer.
-            let cmds_read = count(&buffer[0..read], b'\n');
-            num_stored += cmds_read;
-        }
-
-        // Find how many bytes we need to move backwards
-        // in the file to remove all the old commands.
-        if num_stored >= max_file_size {
-            let mut total_read = 0u64;
-            let mut move_dist = 0u64;
-            file.seek(SeekFrom::Start(0))?;
-
-            let mut eread = 0;
-            loop {
-                // Read 4K of bytes all at once into the buffer.
-                let read = file.read(&mut buffer)?;
-                // If EOF is found, don't seek at all.
-                if read == 0 {
-                    break;
-                }
-                // Count the number of commands that were found in the current buffer
-                let cmds_read = count(&buffer[0..read], b'\n');
-
-                if eread + cmds_read >= num_stored - max_file_size {
-                    for &byte in buffer[0..read].iter() {
-                        total_read += 1;
-                        if byte == b'\n' {
-                            if eread == num_stored - max_file_size {
-                                move_dist = total_read;
-                                break;
-                            }
-                            eread += 1;
-                        }
-                    }
-                    break;
-                } else {
-                    total_read += read as u64;
-                    eread += cmds_read;
-                }
-            }
+/// How `lock()` locks the history file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LockMode {
+    /// For reading. Other sessions can read the file at the same time, but not write it.
+    Shared,
+    /// For writing. Other sessions can't read or write the file at the same time.
+    Exclusive,
+}
 
+/// Locks `file` for other sessions with `flock`, until it is closed.
+#[cfg(unix)]
+fn lock(file: &File, mode: LockMode) -> io::Result<()> {
+    use libc;
+    use std::os::unix::io::AsRawFd;
 
-            // Move it all back
-            move_file_contents_backward(&mut file, move_dist);
-        }
+    let operation = match mode {
+        LockMode::Shared => libc::LOCK_SH,
+        LockMode::Exclusive => libc::LOCK_EX,
     };
+    if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Files aren't locked on other platforms, so sessions sharing a history file may interleave
+/// their writes.
+#[cfg(not(unix))]
+fn lock(_: &File, _: LockMode) -> io::Result<()> {
+    Ok(())
+}
 
+/// Identifies the file `metadata` belongs to, so that a file which was replaced by another can be
+/// told apart from it. None if this isn't known on this platform.
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
 
-    // Seek to end for appending
-    try!(file.seek(SeekFrom::End(0)));
-    // Write the command to the history file.
-    try!(file.write_all(String::from(new_item.clone()).as_bytes()));
-    try!(file.write_all(b"\n"));
-    file.flush()?;
+#[cfg(not(unix))]
+fn file_id(_: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
 
-    Ok(())
+/// Perform write operation. If the history file does not exist, it will be created.
+/// This function is not part of the public interface.
+fn write_to_disk(max_file_size: usize, new_line: &str, file_name: &str) -> io::Result<()> {
+    let mut file = open_locked(file_name, &append_options(), LockMode::Exclusive)?;
+    append_to_file(&mut file, file_name, max_file_size, new_line).map(|_| ())
 }
 
-fn move_file_contents_backward(file: &mut File, distance: u64) -> io::Result<()> {
-    let mut total_read = 0;
-    let mut buffer = [0u8, 4096];
+/// How the history file is opened to be written. Writes always go to the end of the file.
+fn append_options() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    options.read(true).append(true).create(true);
+    options
+}
 
-    file.seek(SeekFrom::Start(distance))?;
-    
+/// Opens the history file `file_name` with `options`, and locks it like `lock()`. Another session
+/// may replace the file while waiting for the lock, in which case the new file is opened instead.
+fn open_locked(file_name: &str, options: &OpenOptions, mode: LockMode) -> io::Result<File> {
     loop {
-        // Read 4K of bytes all at once into the buffer.
-        let read = file.read(&mut buffer)?;
-        total_read += read as u64;
-        // If EOF is found, don't seek at all.
-        if read == 0 {
-            break;
+        let file = options.open(file_name)?;
+        lock(&file, mode)?;
+        let opened = file.metadata()?;
+        match fs::metadata(file_name) {
+            Ok(ref current) if file_id(current) == file_id(&opened) => return Ok(file),
+            Ok(_) => {}
+            Err(ref e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
         }
+    }
+}
 
-        file.seek(SeekFrom::Current(-(read as i64 + distance as i64)));
-
-
-        file.write_all(&buffer[..read])?;
-        file.seek(SeekFrom::Current(distance as i64));
+/// Appends `new_line` to the history file `file_name`, which is open as `file` and locked.
+/// Returns the position of the end of the file afterwards.
+///
+/// If the file would have more than `max_file_size` entries, it is replaced by a copy without the
+/// oldest entries instead, so that it is never left partly rewritten.
+fn append_to_file(
+    file: &mut File,
+    file_name: &str,
+    max_file_size: usize,
+    new_line: &str,
+) -> io::Result<FilePosition> {
+    let mut contents = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_end(&mut contents)?;
+
+    let mut line = String::with_capacity(new_line.len() + 1);
+    line.push_str(new_line);
+    line.push('\n');
+
+    let num_stored = count(&contents, b'\n');
+    if num_stored < max_file_size {
+        // The file is opened for appending, so the command is written at its end at once, and
+        // is never seen half written.
+        file.write_all(line.as_bytes())?;
+        file.flush()?;
+        let metadata = file.metadata()?;
+        return Ok(FilePosition::new(contents.len() + line.len(), &metadata));
     }
 
-    file.set_len(total_read)?;
-    file.flush()?;
+    // Keep the newest `max_file_size - 1` entries, and the new one.
+    let removed = num_stored + 1 - max_file_size;
+    let start = contents
+        .iter()
+        .enumerate()
+        .filter(|&(_, &b)| b == b'\n')
+        .nth(removed - 1)
+        .map_or(contents.len(), |(i, _)| i + 1);
+    let mut new_contents = contents.split_off(start);
+    new_contents.extend_from_slice(line.as_bytes());
+
+    // The copy is written next to the file, so that renaming it over the file is atomic.
+    let tmp_name = format!("{}.{}.tmp", file_name, process::id());
+    let permissions = file.metadata()?.permissions();
+    let res = write_new_file(&tmp_name, &new_contents, permissions).and_then(|metadata| {
+        fs::rename(&tmp_name, file_name)?;
+        Ok(FilePosition::new(new_contents.len(), &metadata))
+    });
+    if res.is_err() {
+        let _ = fs::remove_file(&tmp_name);
+    }
+    res
+}
 
-    Ok(())
-}
\ No newline at end of file
+/// Writes `contents` to a new file `file_name` and syncs it to the disk. Returns the metadata of
+/// the new file.
+fn write_new_file(
+    file_name: &str,
+    contents: &[u8],
+    permissions: Permissions,
+) -> io::Result<fs::Metadata> {
+    let mut file = File::create(file_name)?;
+    file.write_all(contents)?;
+    file.set_permissions(permissions)?;
+    file.sync_all()?;
+    file.metadata()
+}
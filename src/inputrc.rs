@@ -0,0 +1,629 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, Read};
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use termion::event::{self, Event, Key};
+
+use BindingMode;
+use Context;
+use KeyBindings;
+use unsupported_key_sequence;
+
+// `$include` directives nested deeper than this are ignored, in case a file includes itself.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+const ESC: u8 = 0x1B;
+
+/// Reads GNU readline's inputrc configuration files, and applies them to a `Context`.
+///
+/// The following are supported:
+///
+/// - Key bindings, written either as `"\C-x\C-e": edit-command-line` or as
+///   `Control-u: unix-line-discard`. The key is bound in `Context::key_binding_table`, whether or
+///   not a command with that name exists in `Context::commands`, so commands can be registered
+///   after the file is loaded. Until then the key does nothing. Binding a key to a quoted string
+///   registers a command which inserts the string. Binding a key to `self-insert` unbinds it.
+/// - `set editing-mode` and `set keymap`, which choose the key bindings and where further
+///   bindings are added.
+/// - `set history-size`.
+/// - `set show-all-if-ambiguous`, `set disable-completion` and `set completion-ignore-case`,
+///   which set `Context::completion_options`.
+/// - `$if mode=...`, `$if term=...` and `$if Application`, with `$else` and `$endif`.
+/// - `$include`.
+///
+/// As in readline, unknown variables and lines which can't be parsed are ignored.
+///
+/// ```no_run
+/// use liner::*;
+///
+/// let mut context = Context::new();
+/// Inputrc::new("myshell").load_default(&mut context).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Inputrc {
+    /// The name matched by `$if Name` conditionals, usually the name of the program.
+    pub application: String,
+    /// The terminal type matched by `$if term=...` conditionals. Defaults to `$TERM`.
+    pub term: String,
+}
+
+impl Inputrc {
+    pub fn new<S: Into<String>>(application: S) -> Self {
+        Inputrc {
+            application: application.into(),
+            term: env::var("TERM").unwrap_or_default(),
+        }
+    }
+
+    /// Loads the file named by `$INPUTRC`, `~/.inputrc` or `/etc/inputrc`, whichever exists
+    /// first. Does nothing if none of them exist.
+    pub fn load_default(&self, context: &mut Context) -> io::Result<()> {
+        let mut paths = Vec::new();
+        if let Some(path) = env::var_os("INPUTRC") {
+            paths.push(PathBuf::from(path));
+        }
+        if let Some(home) = env::var_os("HOME") {
+            paths.push(Path::new(&home).join(".inputrc"));
+        }
+        paths.push(PathBuf::from("/etc/inputrc"));
+
+        match paths.into_iter().find(|p| p.is_file()) {
+            Some(path) => self.load_file(context, path),
+            None => Ok(()),
+        }
+    }
+
+    /// Loads an inputrc file.
+    pub fn load_file<P: AsRef<Path>>(&self, context: &mut Context, path: P) -> io::Result<()> {
+        Parser::new(self, context).load_file(path.as_ref(), 0)
+    }
+
+    /// Loads inputrc directives from a string. Relative `$include` paths are relative to the
+    /// current directory.
+    pub fn load_str(&self, context: &mut Context, contents: &str) {
+        Parser::new(self, context).parse(contents, 0)
+    }
+}
+
+struct Parser<'a, 'b> {
+    inputrc: &'a Inputrc,
+    context: &'b mut Context,
+
+    // Where key bindings are added, chosen with `set keymap`.
+    mode: BindingMode,
+
+    // Bytes which come before every bound key sequence. The `emacs-meta` and `emacs-ctlx` keymaps
+    // bind keys after ESC and Ctrl-X.
+    prefix: Vec<u8>,
+}
+
+impl<'a, 'b> Parser<'a, 'b> {
+    fn new(inputrc: &'a Inputrc, context: &'b mut Context) -> Self {
+        let mode = match context.key_bindings {
+            KeyBindings::Emacs => BindingMode::Emacs,
+            KeyBindings::Vi => BindingMode::ViInsert,
+        };
+
+        Parser {
+            inputrc: inputrc,
+            context: context,
+            mode: mode,
+            prefix: Vec::new(),
+        }
+    }
+
+    fn load_file(&mut self, path: &Path, depth: usize) -> io::Result<()> {
+        let mut contents = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut contents));
+        self.parse(&contents, depth);
+        Ok(())
+    }
+
+    fn parse(&mut self, contents: &str, depth: usize) {
+        // For every `$if` we're in: whether the enclosing block is used, and whether the condition
+        // holds (flipped by `$else`).
+        let mut conds: Vec<(bool, bool)> = Vec::new();
+
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let active = conds.last().map_or(true, |&(outer, cond)| outer && cond);
+
+            if line.starts_with('$') {
+                let (directive, arg) = split_word(&line[1..]);
+                match directive {
+                    "if" => conds.push((active, self.test(arg))),
+                    "else" => if let Some(cond) = conds.last_mut() {
+                        cond.1 = !cond.1;
+                    },
+                    "endif" => {
+                        conds.pop();
+                    }
+                    "include" if active && depth < MAX_INCLUDE_DEPTH => {
+                        // readline ignores files which can't be read here too
+                        let _ = self.load_file(&expand_tilde(arg), depth + 1);
+                    }
+                    _ => {}
+                }
+            } else if active {
+                let (word, rest) = split_word(line);
+                if word == "set" {
+                    let (name, value) = split_word(rest);
+                    self.set(name, split_word(value).0);
+                } else {
+                    self.bind(line);
+                }
+            }
+        }
+    }
+
+    fn test(&self, arg: &str) -> bool {
+        if arg.starts_with("mode=") {
+            match &arg[5..] {
+                "emacs" => self.context.key_bindings == KeyBindings::Emacs,
+                "vi" => self.context.key_bindings == KeyBindings::Vi,
+                _ => false,
+            }
+        } else if arg.starts_with("term=") {
+            // matches either the whole terminal name or the part before the first `-`
+            let term = &self.inputrc.term;
+            let name = &arg[5..];
+            term == name || term.split('-').next() == Some(name)
+        } else {
+            arg.eq_ignore_ascii_case(&self.inputrc.application)
+        }
+    }
+
+    fn set(&mut self, name: &str, value: &str) {
+        let on = value.is_empty() || value.eq_ignore_ascii_case("on") || value == "1";
+
+        match &name.to_ascii_lowercase()[..] {
+            "editing-mode" => match value {
+                "emacs" => {
+                    self.context.key_bindings = KeyBindings::Emacs;
+                    self.set_keymap(BindingMode::Emacs, &[]);
+                }
+                "vi" => {
+                    self.context.key_bindings = KeyBindings::Vi;
+                    self.set_keymap(BindingMode::ViInsert, &[]);
+                }
+                _ => {}
+            },
+            "keymap" => match value {
+                "emacs" | "emacs-standard" => self.set_keymap(BindingMode::Emacs, &[]),
+                "emacs-meta" => self.set_keymap(BindingMode::Emacs, &[ESC]),
+                "emacs-ctlx" => self.set_keymap(BindingMode::Emacs, &[ctrl(b'x')]),
+                "vi" | "vi-move" | "vi-command" => self.set_keymap(BindingMode::ViNormal, &[]),
+                "vi-insert" => self.set_keymap(BindingMode::ViInsert, &[]),
+                _ => {}
+            },
+            "history-size" => if let Ok(size) = value.parse::<isize>() {
+                // a negative size means the history is unlimited
+                let size = if size < 0 { usize::max_value() } else { size as usize };
+                self.context.history.set_max_size(size);
+                self.context.history.set_max_file_size(size);
+            },
+            "show-all-if-ambiguous" => self.context.completion_options.show_all_if_ambiguous = on,
+            "disable-completion" => self.context.completion_options.disable_completion = on,
+            "completion-ignore-case" => self.context.completion_options.ignore_case = on,
+            _ => {}
+        }
+    }
+
+    fn set_keymap(&mut self, mode: BindingMode, prefix: &[u8]) {
+        self.mode = mode;
+        self.prefix = prefix.to_vec();
+    }
+
+    fn bind(&mut self, line: &str) {
+        let (seq, rest) = if line.starts_with('"') {
+            match split_quoted(line) {
+                Some((seq, rest)) => (parse_key_seq(seq), rest),
+                None => return,
+            }
+        } else {
+            match line.find(':') {
+                Some(i) => match parse_key_name(line[..i].trim()) {
+                    Some(seq) => (seq, &line[i..]),
+                    None => return,
+                },
+                None => return,
+            }
+        };
+
+        let rest = rest.trim_start();
+        if !rest.starts_with(':') {
+            return;
+        }
+        let value = rest[1..].trim_start();
+
+        let mut bytes = self.prefix.clone();
+        bytes.extend(seq);
+        let keys = match keys_from_bytes(&bytes) {
+            Some(ref keys) if !keys.is_empty() => keys.clone(),
+            _ => return,
+        };
+
+        let command = if value.starts_with('"') || value.starts_with('\'') {
+            let text = match split_quoted(value) {
+                Some((text, _)) => text,
+                None => return,
+            };
+
+            // The macro is registered as a command named after the quoted text.
+            let name = format!("\"{}\"", text);
+            let text = String::from_utf8_lossy(&parse_key_seq(text)).into_owned();
+            self.context
+                .commands
+                .register(name.clone(), move |ed| ed.insert_str_after_cursor(&text));
+            name
+        } else {
+            match split_word(value).0 {
+                "" => return,
+                "self-insert" => {
+                    self.context.key_binding_table.unbind(self.mode, &keys);
+                    return;
+                }
+                name => name.to_owned(),
+            }
+        };
+
+        self.context.key_binding_table.bind(self.mode, &keys, command);
+    }
+}
+
+/// Splits off the first whitespace-separated word of `s`.
+fn split_word(s: &str) -> (&str, &str) {
+    let s = s.trim_start();
+    match s.find(char::is_whitespace) {
+        Some(i) => (&s[..i], s[i..].trim_start()),
+        None => (s, ""),
+    }
+}
+
+/// Splits a string starting with a quote into the quoted text, and the text after the closing
+/// quote.
+fn split_quoted(s: &str) -> Option<(&str, &str)> {
+    let quote = s.chars().next()?;
+    let mut escaped = false;
+
+    for (i, c) in s.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return Some((&s[1..i], &s[i + 1..]));
+        }
+    }
+
+    None
+}
+
+fn expand_tilde(path: &str) -> PathBuf {
+    if path.starts_with("~/") {
+        if let Some(home) = env::var_os("HOME") {
+            return Path::new(&home).join(&path[2..]);
+        }
+    }
+    PathBuf::from(path)
+}
+
+fn ctrl(b: u8) -> u8 {
+    if b == b'?' {
+        0x7F
+    } else {
+        b & 0x1F
+    }
+}
+
+fn push_char(bytes: &mut Vec<u8>, c: char) {
+    let mut buf = [0; 4];
+    bytes.extend(c.encode_utf8(&mut buf).as_bytes());
+}
+
+/// Parses the text of a quoted key sequence, such as `\C-x\C-e`, into the bytes the terminal
+/// sends for it.
+fn parse_key_seq(seq: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut chars = seq.chars().peekable();
+    while chars.peek().is_some() {
+        parse_seq_key(&mut chars, &mut bytes);
+    }
+    bytes
+}
+
+/// Parses one key of a key sequence, such as `a`, `\t` or `\C-\M-x`.
+fn parse_seq_key<I: Iterator<Item = char>>(chars: &mut Peekable<I>, bytes: &mut Vec<u8>) {
+    let c = match chars.next() {
+        Some('\\') => match chars.next() {
+            Some(c) => c,
+            None => return bytes.push(b'\\'),
+        },
+        Some(c) => return push_char(bytes, c),
+        None => return,
+    };
+
+    match c {
+        'C' | 'M' if chars.peek() == Some(&'-') => {
+            chars.next();
+            if c == 'M' {
+                bytes.push(ESC);
+                parse_seq_key(chars, bytes);
+            } else {
+                let start = bytes.len();
+                parse_seq_key(chars, bytes);
+                if let Some(last) = bytes[start..].last_mut() {
+                    *last = ctrl(*last);
+                }
+            }
+        }
+        'e' => bytes.push(ESC),
+        'a' => bytes.push(0x07),
+        'b' => bytes.push(0x08),
+        'd' => bytes.push(0x7F),
+        'f' => bytes.push(0x0C),
+        'n' => bytes.push(b'\n'),
+        'r' => bytes.push(b'\r'),
+        't' => bytes.push(b'\t'),
+        'v' => bytes.push(0x0B),
+        '0'..='7' => {
+            let mut n = c.to_digit(8).unwrap();
+            for _ in 0..2 {
+                match chars.peek().and_then(|c| c.to_digit(8)) {
+                    Some(d) => n = n * 8 + d,
+                    None => break,
+                }
+                chars.next();
+            }
+            bytes.push(n as u8);
+        }
+        'x' => {
+            let mut n = 0;
+            for _ in 0..2 {
+                match chars.peek().and_then(|c| c.to_digit(16)) {
+                    Some(d) => n = n * 16 + d,
+                    None => break,
+                }
+                chars.next();
+            }
+            bytes.push(n as u8);
+        }
+        c => push_char(bytes, c),
+    }
+}
+
+/// Parses a key name, such as `Control-u` or `Meta-Rubout`, into the bytes the terminal sends
+/// for it.
+fn parse_key_name(name: &str) -> Option<Vec<u8>> {
+    let mut name = name;
+    let mut control = false;
+    let mut meta = false;
+
+    loop {
+        let lower = name.to_ascii_lowercase();
+        let (modifier, len) = if lower.starts_with("control-") {
+            (&mut control, 8)
+        } else if lower.starts_with("meta-") {
+            (&mut meta, 5)
+        } else if lower.starts_with("c-") && name.len() > 2 {
+            (&mut control, 2)
+        } else if lower.starts_with("m-") && name.len() > 2 {
+            (&mut meta, 2)
+        } else {
+            break;
+        };
+        *modifier = true;
+        name = &name[len..];
+    }
+
+    let mut bytes = match &name.to_ascii_lowercase()[..] {
+        "del" | "rubout" => vec![0x7F],
+        "esc" | "escape" => vec![ESC],
+        "lfd" | "newline" => vec![b'\n'],
+        "ret" | "return" => vec![b'\r'],
+        "spc" | "space" => vec![b' '],
+        "tab" => vec![b'\t'],
+        _ => {
+            let mut bytes = Vec::new();
+            push_char(&mut bytes, name.chars().next()?);
+            bytes
+        }
+    };
+
+    if control {
+        bytes[0] = ctrl(bytes[0]);
+    }
+    if meta {
+        bytes.insert(0, ESC);
+    }
+    Some(bytes)
+}
+
+/// Converts the bytes sent by the terminal into the keys termion reads from them.
+fn keys_from_bytes(bytes: &[u8]) -> Option<Vec<Key>> {
+    let mut iter = bytes.iter().map(|&b| Ok(b)).peekable();
+    let mut keys = Vec::new();
+
+    loop {
+        let start = bytes.len() - iter.len();
+        let b = match iter.next() {
+            Some(Ok(b)) => b,
+            _ => break,
+        };
+        // termion only reads ESC on its own when nothing follows it
+        if b == ESC && iter.peek().is_none() {
+            keys.push(Key::Esc);
+            continue;
+        }
+
+        match event::parse_event(b, &mut iter) {
+            Ok(Event::Key(key)) => keys.push(key),
+            // termion reads an escape sequence it doesn't know, and whatever was sent with it,
+            // as `Event::Unsupported`
+            Err(_) if b == ESC => {
+                keys.extend(unsupported_key_sequence(&bytes[start..]));
+                break;
+            }
+            _ => return None,
+        }
+    }
+
+    Some(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use Editor;
+
+    fn keys(seq: &str) -> Vec<Key> {
+        keys_from_bytes(&parse_key_seq(seq)).unwrap()
+    }
+
+    #[test]
+    fn key_sequences() {
+        assert_eq!(keys(r"\C-x\C-e"), vec![Key::Ctrl('x'), Key::Ctrl('e')]);
+        assert_eq!(keys(r"\M-f"), vec![Key::Alt('f')]);
+        assert_eq!(keys(r"\e\C-h"), vec![Key::Alt('\x08')]);
+        assert_eq!(keys(r"\e[A"), vec![Key::Up]);
+        assert_eq!(keys(r"\e"), vec![Key::Esc]);
+        assert_eq!(keys(r"\e[1;5C"), unsupported_key_sequence(b"\x1b[1;5C"));
+        assert_eq!(keys(r"\C-?"), vec![Key::Backspace]);
+        assert_eq!(keys(r#"\t\\\""#), vec![Key::Char('\t'), Key::Char('\\'), Key::Char('"')]);
+        assert_eq!(keys(r"\001\x02é"), vec![Key::Ctrl('a'), Key::Ctrl('b'), Key::Char('é')]);
+    }
+
+    #[test]
+    fn key_names() {
+        let key = |name| keys_from_bytes(&parse_key_name(name).unwrap()).unwrap();
+        assert_eq!(key("Control-u"), vec![Key::Ctrl('u')]);
+        assert_eq!(key("C-u"), vec![Key::Ctrl('u')]);
+        assert_eq!(key("Meta-Rubout"), vec![Key::Alt('\x7F')]);
+        assert_eq!(key("M-C-h"), vec![Key::Alt('\x08')]);
+        assert_eq!(key("TAB"), vec![Key::Char('\t')]);
+        assert_eq!(key("x"), vec![Key::Char('x')]);
+    }
+
+    #[test]
+    fn bindings_and_settings() {
+        let mut context = Context::new();
+        Inputrc::new("test").load_str(&mut context, r#"
+            # comment
+            "\C-x\C-e": edit-command-line
+            Meta-u: upcase-word
+            "\e[A": history-search-backward
+            set completion-ignore-case on
+            set keymap emacs-ctlx
+            u: undo
+            set history-size 10
+            set show-all-if-ambiguous on
+            set editing-mode vi
+            "\C-l": clear-screen
+            set keymap vi-command
+            "\C-e": end-of-line   # trailing text
+            "\C-p": self-insert
+        "#);
+
+        let table = &context.key_binding_table;
+        assert_eq!(
+            table.get(BindingMode::Emacs, &[Key::Ctrl('x'), Key::Ctrl('e')]),
+            Some("edit-command-line")
+        );
+        assert_eq!(table.get(BindingMode::Emacs, &[Key::Alt('u')]), Some("upcase-word"));
+        assert_eq!(table.get(BindingMode::Emacs, &[Key::Up]), Some("history-search-backward"));
+        assert_eq!(table.get(BindingMode::Emacs, &[Key::Ctrl('x'), Key::Char('u')]), Some("undo"));
+        assert_eq!(table.get(BindingMode::ViInsert, &[Key::Ctrl('l')]), Some("clear-screen"));
+        assert_eq!(table.get(BindingMode::ViNormal, &[Key::Ctrl('e')]), Some("end-of-line"));
+        assert_eq!(table.get(BindingMode::ViNormal, &[Key::Ctrl('p')]), None);
+        assert_eq!(context.key_bindings, KeyBindings::Vi);
+        assert!(context.completion_options.show_all_if_ambiguous);
+        assert!(context.completion_options.ignore_case);
+
+        for i in 0..20 {
+            context.history.push(format!("{}", i).into()).unwrap();
+        }
+        assert_eq!(context.history.len(), 10);
+    }
+
+    #[test]
+    fn conditionals() {
+        let mut context = Context::new();
+        let mut inputrc = Inputrc::new("Test");
+        inputrc.term = "xterm-256color".into();
+        inputrc.load_str(&mut context, r#"
+            $if mode=emacs
+                "a": one
+                $if test
+                    "b": two
+                $else
+                    "b": not-two
+                $endif
+            $else
+                "c": not-three
+            $endif
+            $if mode=vi
+                "d": not-four
+                $if Test
+                    "e": not-five
+                $endif
+            $endif
+            $if term=xterm
+                "f": six
+            $endif
+            $if term=rxvt
+                "g": not-seven
+            $endif
+        "#);
+
+        let bound = |c| context.key_binding_table.get(BindingMode::Emacs, &[Key::Char(c)]);
+        assert_eq!(bound('a'), Some("one"));
+        assert_eq!(bound('b'), Some("two"));
+        assert_eq!(bound('c'), None);
+        assert_eq!(bound('d'), None);
+        assert_eq!(bound('e'), None);
+        assert_eq!(bound('f'), Some("six"));
+        assert_eq!(bound('g'), None);
+    }
+
+    #[test]
+    fn include() {
+        let mut tmp_file = env::temp_dir();
+        tmp_file.push("liner_inputrc_include_test");
+        {
+            let mut f = fs::File::create(&tmp_file).unwrap();
+            writeln!(f, "\"\\C-t\": transpose-chars").unwrap();
+        }
+
+        let mut context = Context::new();
+        Inputrc::new("test").load_str(&mut context, &format!("$include {}", tmp_file.display()));
+        fs::remove_file(&tmp_file).unwrap();
+
+        assert_eq!(
+            context.key_binding_table.get(BindingMode::Emacs, &[Key::Ctrl('t')]),
+            Some("transpose-chars")
+        );
+    }
+
+    #[test]
+    fn macro_inserts_text() {
+        let mut context = Context::new();
+        Inputrc::new("test").load_str(&mut context, r#""\C-t": "hello\tworld""#);
+
+        let name = context
+            .key_binding_table
+            .get(BindingMode::Emacs, &[Key::Ctrl('t')])
+            .unwrap()
+            .to_owned();
+        let out = Vec::new();
+        let mut ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        ed.run_command(&name).unwrap();
+        assert_eq!(String::from(ed), "hello\tworld");
+    }
+}
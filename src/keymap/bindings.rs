@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use termion::event::Key;
+
+/// Which set of bindings in a `KeyBindingTable` applies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BindingMode {
+    /// Used by the Emacs keymap.
+    Emacs,
+    /// Used by the Vi keymap in insert mode.
+    ViInsert,
+    /// Used by the Vi keymap outside of insert mode. These bindings take precedence over the
+    /// built-in Vi commands.
+    ViNormal,
+    /// Used by both keymaps while the completion menu is shown. Only single keys are looked up.
+    /// A key which isn't bound, or isn't bound to a command in `Context::commands`, accepts the
+    /// selected completion and is then handled as usual.
+    Menu,
+}
+
+/// What a key sequence is bound to in a `KeyBindingTable`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Binding {
+    /// The sequence is bound to the named command.
+    Command(String),
+    /// The sequence is the start of one or more longer bound sequences. It may be bound to a
+    /// command itself as well, which runs if the longer sequences are not completed.
+    Prefix,
+    /// Nothing is bound to the sequence.
+    Unbound,
+}
+
+/// The keys which the sequence `bytes`, sent by the terminal for a key termion doesn't know, is
+/// bound as. Each escape character is `Key::Esc`, and the other characters are `Key::Char`.
+///
+/// For example, most terminals send `"\x1b[1;5C"` for ctrl-right, which is bound as
+/// `[Key::Esc, Key::Char('['), Key::Char('1'), Key::Char(';'), Key::Char('5'), Key::Char('C')]`.
+/// The whole sequence is looked up at once, so it runs nothing if it is not bound itself.
+pub fn unsupported_key_sequence(bytes: &[u8]) -> Vec<Key> {
+    String::from_utf8_lossy(bytes)
+        .chars()
+        .map(|c| if c == '\x1b' { Key::Esc } else { Key::Char(c) })
+        .collect()
+}
+
+/// Maps key sequences to the names of editor commands, such as `"kill-line"`.
+///
+/// Keys which are not bound to anything are handled by the keymap itself. In the Emacs keymap,
+/// this means that unbound characters are inserted into the buffer.
+///
+/// A sequence can be bound even if it is the start of a longer bound sequence. For example,
+/// `Ctrl-X` is bound to `"undo"` by default, but binding `Ctrl-X Ctrl-E` as well makes the keymap
+/// wait for the key after `Ctrl-X`. If that key does not complete a longer sequence, `"undo"`
+/// runs and the key is then handled on its own.
+///
+/// ```
+/// extern crate liner;
+/// extern crate termion;
+///
+/// use liner::*;
+/// use termion::event::Key;
+///
+/// # fn main() {
+/// let mut context = Context::new();
+/// context.key_binding_table.bind(BindingMode::Emacs, &[Key::Ctrl('t')], "clear-screen");
+/// context.key_binding_table.bind(
+///     BindingMode::Emacs,
+///     &[Key::Ctrl('x'), Key::Ctrl('u')],
+///     "undo",
+/// );
+/// context.key_binding_table.unbind(BindingMode::Emacs, &[Key::Ctrl('l')]);
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct KeyBindingTable {
+    bindings: HashMap<BindingMode, HashMap<Vec<Key>, String>>,
+}
+
+impl KeyBindingTable {
+    /// Creates a table with the default Emacs and Vi bindings.
+    pub fn new() -> Self {
+        let mut table = KeyBindingTable::empty();
+
+        // Bindings for keys which behave the same way in every keymap and mode.
+        for &mode in &[BindingMode::Emacs, BindingMode::ViInsert, BindingMode::ViNormal] {
+            table.bind(mode, &[Key::Char('\n')], "accept-line");
+            table.bind(mode, &[Key::Char('\t')], "complete");
+            table.bind(mode, &[Key::BackTab], "menu-complete-backward");
+            table.bind(mode, &[Key::Ctrl('c')], "interrupt");
+            table.bind(mode, &[Key::Ctrl('h')], "backward-delete-char");
+            table.bind(mode, &[Key::Ctrl('f')], "accept-autosuggestion");
+            table.bind(mode, &[Key::Right], "forward-char");
+
+            let unsupported: [(&[u8], &str); 5] = [
+                // ctrl-right and ctrl-left
+                (b"\x1b[1;5C", "forward-word"),
+                (b"\x1b[5C", "forward-word"),
+                (b"\x1b[1;5D", "backward-word"),
+                (b"\x1b[5D", "backward-word"),
+                // alt-right
+                (b"\x1b[1;3C", "accept-autosuggestion-char"),
+            ];
+            for &(seq, command) in unsupported.iter() {
+                table.bind(mode, &unsupported_key_sequence(seq), command);
+            }
+        }
+
+        table.bind(BindingMode::ViInsert, &[Key::Ctrl('d')], "end-of-file");
+        table.bind(BindingMode::ViInsert, &[Key::Alt('f')], "forward-word");
+        table.bind(BindingMode::ViNormal, &[Key::Ctrl('d')], "end-of-file");
+
+        let emacs = [
+            (Key::Ctrl('d'), "delete-char-or-eof"),
+            (Key::Ctrl('l'), "clear-screen"),
+            (Key::Ctrl('a'), "beginning-of-line"),
+            (Key::Ctrl('e'), "end-of-line"),
+            (Key::Ctrl('b'), "backward-char"),
+            (Key::Ctrl('p'), "previous-history"),
+            (Key::Ctrl('n'), "next-history"),
+            (Key::Ctrl('u'), "unix-line-discard"),
+            (Key::Ctrl('k'), "kill-line"),
+            (Key::Ctrl('w'), "unix-word-rubout"),
+            (Key::Ctrl('y'), "yank"),
+            (Key::Ctrl('r'), "reverse-search-history"),
+            (Key::Ctrl('s'), "forward-search-history"),
+            (Key::Ctrl('g'), "abort"),
+            (Key::Ctrl('x'), "undo"),
+            (Key::Alt('<'), "beginning-of-history"),
+            (Key::Alt('>'), "end-of-history"),
+            (Key::Alt('\x7F'), "backward-kill-word"),
+            (Key::Alt('f'), "forward-word"),
+            (Key::Alt('b'), "backward-word"),
+            (Key::Alt('r'), "revert-line"),
+            (Key::Alt('y'), "yank-pop"),
+            (Key::Alt('.'), "yank-last-arg"),
+            (Key::Left, "backward-char"),
+            (Key::Up, "previous-history"),
+            (Key::Down, "next-history"),
+            (Key::Home, "beginning-of-line"),
+            (Key::End, "end-of-line"),
+            (Key::Backspace, "backward-delete-char"),
+            (Key::Delete, "delete-char"),
+        ];
+        for &(key, command) in emacs.iter() {
+            table.bind(BindingMode::Emacs, &[key], command);
+        }
+
+        let menu = [
+            (Key::Char('\t'), "menu-next"),
+            (Key::Ctrl('n'), "menu-next"),
+            (Key::Right, "menu-next"),
+            (Key::BackTab, "menu-previous"),
+            (Key::Ctrl('p'), "menu-previous"),
+            (Key::Left, "menu-previous"),
+            (Key::Down, "menu-down"),
+            (Key::Up, "menu-up"),
+            (Key::PageDown, "menu-next-page"),
+            (Key::PageUp, "menu-previous-page"),
+            (Key::Esc, "menu-cancel"),
+            (Key::Ctrl('g'), "menu-cancel"),
+            (Key::Char('\n'), "menu-accept"),
+        ];
+        for &(key, command) in menu.iter() {
+            table.bind(BindingMode::Menu, &[key], command);
+        }
+
+        table
+    }
+
+    /// Creates a table without any bindings.
+    pub fn empty() -> Self {
+        KeyBindingTable { bindings: HashMap::new() }
+    }
+
+    /// Binds `keys` to the named command in the given mode, replacing any existing binding for
+    /// `keys`. Returns the name of the command that was previously bound to `keys`, if any.
+    pub fn bind<S: Into<String>>(
+        &mut self,
+        mode: BindingMode,
+        keys: &[Key],
+        command: S,
+    ) -> Option<String> {
+        self.bindings
+            .entry(mode)
+            .or_insert_with(HashMap::new)
+            .insert(keys.to_vec(), command.into())
+    }
+
+    /// Removes the binding for `keys` in the given mode, so that the keys are handled by the
+    /// keymap itself. Returns the name of the command that was bound to `keys`, if any.
+    pub fn unbind(&mut self, mode: BindingMode, keys: &[Key]) -> Option<String> {
+        self.bindings.get_mut(&mode).and_then(|b| b.remove(keys))
+    }
+
+    /// Returns the name of the command bound to `keys` in the given mode.
+    pub fn get(&self, mode: BindingMode, keys: &[Key]) -> Option<&str> {
+        self.bindings
+            .get(&mode)
+            .and_then(|b| b.get(keys))
+            .map(|s| &s[..])
+    }
+
+    /// Looks up what a (possibly incomplete) key sequence is bound to.
+    ///
+    /// Sequences starting with `Key::Esc` are only sent whole, by `unsupported_key_sequence()`, so
+    /// they are never completed from a shorter one. Otherwise `Key::Esc` would wait for the next
+    /// key.
+    pub fn lookup(&self, mode: BindingMode, keys: &[Key]) -> Binding {
+        let is_prefix = self.bindings.get(&mode).map_or(false, |b| {
+            b.keys().any(|seq| {
+                seq.len() > keys.len() && seq.starts_with(keys) && seq[0] != Key::Esc
+            })
+        });
+
+        if is_prefix {
+            Binding::Prefix
+        } else if let Some(command) = self.get(mode, keys) {
+            Binding::Command(command.into())
+        } else {
+            Binding::Unbound
+        }
+    }
+
+    /// Iterates over every key sequence bound in the given mode, and the command it is bound to.
+    pub fn bindings<'a>(&'a self, mode: BindingMode) -> Box<Iterator<Item = (&'a [Key], &'a str)> + 'a> {
+        match self.bindings.get(&mode) {
+            Some(b) => Box::new(b.iter().map(|(k, c)| (&k[..], &c[..]))),
+            None => Box::new(None.into_iter()),
+        }
+    }
+}
+
+impl Default for KeyBindingTable {
+    fn default() -> Self {
+        KeyBindingTable::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_sequence() {
+        let mut table = KeyBindingTable::empty();
+        table.bind(BindingMode::Emacs, &[Key::Ctrl('x'), Key::Ctrl('e')], "edit");
+
+        assert_eq!(table.lookup(BindingMode::Emacs, &[Key::Ctrl('x')]), Binding::Prefix);
+        assert_eq!(
+            table.lookup(BindingMode::Emacs, &[Key::Ctrl('x'), Key::Ctrl('e')]),
+            Binding::Command("edit".into())
+        );
+        assert_eq!(
+            table.lookup(BindingMode::Emacs, &[Key::Ctrl('x'), Key::Ctrl('a')]),
+            Binding::Unbound
+        );
+        assert_eq!(table.lookup(BindingMode::ViInsert, &[Key::Ctrl('x')]), Binding::Unbound);
+
+        // a bound sequence is still a prefix of longer ones
+        table.bind(BindingMode::Emacs, &[Key::Ctrl('x')], "undo");
+        assert_eq!(table.lookup(BindingMode::Emacs, &[Key::Ctrl('x')]), Binding::Prefix);
+        assert_eq!(table.get(BindingMode::Emacs, &[Key::Ctrl('x')]), Some("undo"));
+    }
+
+    #[test]
+    fn rebind_and_unbind() {
+        let mut table = KeyBindingTable::new();
+        assert_eq!(table.get(BindingMode::Emacs, &[Key::Ctrl('k')]), Some("kill-line"));
+
+        let old = table.bind(BindingMode::Emacs, &[Key::Ctrl('k')], "unix-line-discard");
+        assert_eq!(old, Some("kill-line".into()));
+        assert_eq!(table.get(BindingMode::Emacs, &[Key::Ctrl('k')]), Some("unix-line-discard"));
+
+        table.unbind(BindingMode::Emacs, &[Key::Ctrl('k')]);
+        assert_eq!(table.lookup(BindingMode::Emacs, &[Key::Ctrl('k')]), Binding::Unbound);
+    }
+
+    #[test]
+    fn unsupported_keys() {
+        let table = KeyBindingTable::new();
+        let ctrl_right = unsupported_key_sequence(b"\x1b[1;5C");
+        assert_eq!(ctrl_right[..2], [Key::Esc, Key::Char('[')]);
+        assert_eq!(table.get(BindingMode::ViInsert, &ctrl_right), Some("forward-word"));
+        assert_eq!(table.lookup(BindingMode::ViInsert, &[Key::Esc]), Binding::Unbound);
+    }
+}
@@ -2,8 +2,10 @@ use std::io::{self, Write};
 use termion::event::Key;
 
 use KeyMap;
+use BindingMode;
 use Editor;
 use CursorPosition;
+use SearchDirection;
 
 /// Emacs keybindings for `Editor`. This is the default for `Context::read_line()`.
 ///
@@ -15,48 +17,12 @@ use CursorPosition;
 pub struct Emacs<'a, W: Write> {
     ed: Editor<'a, W>,
     last_arg_fetch_index: Option<usize>,
+    pending_keys: Vec<Key>,
 }
 
 impl<'a, W: Write> Emacs<'a, W> {
     pub fn new(ed: Editor<'a, W>) -> Self {
-        Emacs { ed, last_arg_fetch_index: None }
-    }
-
-    fn handle_ctrl_key(&mut self, c: char) -> io::Result<()> {
-        match c {
-            'l' => self.ed.clear(),
-            'a' => self.ed.move_cursor_to_start_of_line(),
-            'e' => self.ed.move_cursor_to_end_of_line(),
-            'b' => self.ed.move_cursor_left(1),
-            'f' => self.ed.move_cursor_right(1),
-            'd' => self.ed.delete_after_cursor(),
-            'p' => self.ed.move_up(),
-            'n' => self.ed.move_down(),
-            'u' => self.ed.delete_all_before_cursor(),
-            'k' => self.ed.delete_all_after_cursor(),
-            'w' => self.ed.delete_word_before_cursor(true),
-            'x' => {
-                try!(self.ed.undo());
-                Ok(())
-            }
-            _ => Ok(()),
-        }
-    }
-
-    fn handle_alt_key(&mut self, c: char) -> io::Result<()> {
-        match c {
-            '<' => self.ed.move_to_start_of_history(),
-            '>' => self.ed.move_to_end_of_history(),
-            '\x7F' => self.ed.delete_word_before_cursor(true),
-            'f' => emacs_move_word(&mut self.ed, EmacsMoveDir::Right),
-            'b' => emacs_move_word(&mut self.ed, EmacsMoveDir::Left),
-            'r' => {
-                try!(self.ed.revert());
-                Ok(())
-            }
-            '.' => self.handle_last_arg_fetch(),
-            _ => Ok(()),
-        }
+        Emacs { ed, last_arg_fetch_index: None, pending_keys: Vec::new() }
     }
 
     fn handle_last_arg_fetch(&mut self) -> io::Result<()> {
@@ -95,28 +61,76 @@ impl<'a, W: Write> Emacs<'a, W> {
 
 impl<'a, W: Write> KeyMap<'a, W, Emacs<'a, W>> for Emacs<'a, W> {
     fn handle_key_core(&mut self, key: Key) -> io::Result<()> {
-        match key {
-            Key::Alt('.') => {},
-            _ => self.last_arg_fetch_index = None,
-        }
+        self.last_arg_fetch_index = None;
 
         match key {
             Key::Char(c) => self.ed.insert_after_cursor(c),
-            Key::Alt(c) => self.handle_alt_key(c),
-            Key::Ctrl(c) => self.handle_ctrl_key(c),
-            Key::Left => self.ed.move_cursor_left(1),
-            Key::Right => self.ed.move_cursor_right(1),
-            Key::Up => self.ed.move_up(),
-            Key::Down => self.ed.move_down(),
-            Key::Home => self.ed.move_cursor_to_start_of_line(),
-            Key::End => self.ed.move_cursor_to_end_of_line(),
-            Key::Backspace => self.ed.delete_before_cursor(),
-            Key::Delete => self.ed.delete_after_cursor(),
-            Key::Null => Ok(()),
             _ => Ok(()),
         }
     }
 
+    fn binding_mode(&self) -> BindingMode {
+        BindingMode::Emacs
+    }
+
+    fn pending_keys(&mut self) -> &mut Vec<Key> {
+        &mut self.pending_keys
+    }
+
+    fn run_keymap_command(&mut self, name: &str) -> io::Result<bool> {
+        // Fetching the last arg again only replaces the previous fetch if nothing else happened
+        // in between.
+        if name != "yank-last-arg" {
+            self.last_arg_fetch_index = None;
+        }
+
+        match name {
+            "yank-last-arg" => try!(self.handle_last_arg_fetch()),
+            "forward-word" => {
+                // at the end of the line, this accepts the next word of the autosuggestion
+                if self.ed.is_currently_showing_autosuggestion() &&
+                    self.ed.cursor_is_at_end_of_line()
+                {
+                    try!(self.ed.accept_autosuggestion_word());
+                } else {
+                    try!(emacs_move_word(&mut self.ed, EmacsMoveDir::Right));
+                }
+            }
+            "backward-word" => try!(emacs_move_word(&mut self.ed, EmacsMoveDir::Left)),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn handle_key_search(&mut self, key: Key) -> io::Result<bool> {
+        let command = self.ed
+            .context()
+            .key_binding_table
+            .get(BindingMode::Emacs, &[key])
+            .map(|c| c.to_owned());
+
+        match (key, command.as_ref().map(|c| &c[..])) {
+            (_, Some("reverse-search-history")) => {
+                try!(self.ed.search(SearchDirection::Backward));
+            }
+            (_, Some("forward-search-history")) => {
+                try!(self.ed.search(SearchDirection::Forward));
+            }
+            (_, Some("backward-delete-char")) => try!(self.ed.search_delete_before_cursor()),
+            (_, Some("abort")) => try!(self.ed.abort_search()),
+            (Key::Esc, _) => try!(self.ed.accept_search()),
+            // characters which don't run a command are added to the search
+            (Key::Char(c), None) => try!(self.ed.search_insert_char(c)),
+            // anything else ends the search, then is handled as usual
+            _ => {
+                try!(self.ed.accept_search());
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
     fn editor_mut(&mut self) ->  &mut Editor<'a, W> {
         &mut self.ed
     }
@@ -184,6 +198,8 @@ fn emacs_move_word<W: Write>(ed: &mut Editor<W>, direction: EmacsMoveDir) -> io:
 mod tests {
     use super::*;
     use termion::event::Key;
+    use BasicCompleter;
+    use BindingMode;
     use Context;
     use Editor;
     use KeyMap;
@@ -284,4 +300,326 @@ mod tests {
         assert_eq!(res.is_ok(), true);
         assert_eq!(map.ed.current_buffer().to_string(), "not empt".to_string());
     }
+
+    #[test]
+    fn reverse_search() {
+        let mut context = Context::new();
+        context.history.push("git commit".into()).unwrap();
+        context.history.push("ls".into()).unwrap();
+        context.history.push("git push".into()).unwrap();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Emacs::new(ed);
+
+        simulate_keys!(map, [Key::Ctrl('r'), Key::Char('g'), Key::Char('i')]);
+        assert!(map.ed.is_searching());
+        assert_eq!(map.ed.current_history_location(), Some(2));
+        assert_eq!(map.ed.cursor(), 0);
+
+        // repeated ctrl-r moves to older matches
+        simulate_keys!(map, [Key::Ctrl('r')]);
+        assert_eq!(map.ed.current_history_location(), Some(0));
+
+        // esc accepts the match into the edit buffer
+        simulate_keys!(map, [Key::Esc]);
+        assert!(!map.ed.is_searching());
+        assert_eq!(map.ed.current_buffer().to_string(), "git commit");
+    }
+
+    #[test]
+    fn search_matches_within_entry() {
+        let mut context = Context::new();
+        context.history.push("echo hello".into()).unwrap();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Emacs::new(ed);
+
+        simulate_keys!(map, [Key::Ctrl('r'), Key::Char('l'), Key::Char('o')]);
+        assert_eq!(map.ed.current_history_location(), Some(0));
+        assert_eq!(map.ed.cursor(), 8);
+
+        // backspace searches again with the shorter query
+        simulate_keys!(map, [Key::Backspace]);
+        assert_eq!(map.ed.cursor(), 7);
+    }
+
+    #[test]
+    fn search_repeats_within_entry() {
+        let mut context = Context::new();
+        context.history.push("make && make install".into()).unwrap();
+        context.history.push("ls".into()).unwrap();
+        context.history.push("make test".into()).unwrap();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Emacs::new(ed);
+
+        simulate_keys!(map, [Key::Ctrl('r'), Key::Char('m'), Key::Char('a')]);
+        assert_eq!(map.ed.current_history_location(), Some(2));
+        assert_eq!(map.ed.cursor(), 0);
+
+        // repeated ctrl-r finds the later matches in an entry before moving to older entries
+        simulate_keys!(map, [Key::Ctrl('r')]);
+        assert_eq!(map.ed.current_history_location(), Some(0));
+        assert_eq!(map.ed.cursor(), 0);
+        simulate_keys!(map, [Key::Ctrl('r')]);
+        assert_eq!(map.ed.current_history_location(), Some(0));
+        assert_eq!(map.ed.cursor(), 8);
+    }
+
+    #[test]
+    fn search_cancel() {
+        let mut context = Context::new();
+        context.history.push("git commit".into()).unwrap();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Emacs::new(ed);
+        map.ed.insert_str_after_cursor("new").unwrap();
+
+        simulate_keys!(map, [Key::Ctrl('r'), Key::Char('g'), Key::Ctrl('g')]);
+        assert!(!map.ed.is_searching());
+        assert_eq!(map.ed.current_history_location(), None);
+        assert_eq!(map.ed.cursor(), 3);
+        assert_eq!(String::from(map), "new");
+    }
+
+    #[test]
+    fn search_failed() {
+        let mut context = Context::new();
+        context.history.push("git commit".into()).unwrap();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Emacs::new(ed);
+
+        simulate_keys!(map, [Key::Ctrl('r'), Key::Char('g'), Key::Char('x')]);
+        assert!(map.ed.is_searching());
+        // the last successful match stays in the editor
+        assert_eq!(map.ed.current_history_location(), Some(0));
+    }
+
+    #[test]
+    fn search_enter_accepts_line() {
+        let mut context = Context::new();
+        context.history.push("git commit".into()).unwrap();
+        context.history.push("ls".into()).unwrap();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Emacs::new(ed);
+
+        assert!(simulate_keys!(map, [Key::Ctrl('r'), Key::Char('c'), Key::Char('\n')]));
+        assert_eq!(String::from(map), "git commit");
+    }
+
+    #[test]
+    fn search_reuses_last_query() {
+        let mut context = Context::new();
+        context.history.push("git commit".into()).unwrap();
+        context.history.push("git push".into()).unwrap();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Emacs::new(ed);
+
+        simulate_keys!(map, [Key::Ctrl('r'), Key::Char('g'), Key::Esc]);
+        simulate_keys!(map, [Key::Ctrl('e'), Key::Ctrl('r'), Key::Ctrl('r')]);
+        assert_eq!(map.ed.current_history_location(), Some(0));
+
+        // ctrl-s searches forward again
+        simulate_keys!(map, [Key::Ctrl('s')]);
+        assert_eq!(map.ed.current_history_location(), Some(1));
+    }
+
+    #[test]
+    fn rebind_key() {
+        let mut context = Context::new();
+        context.key_binding_table.bind(BindingMode::Emacs, &[Key::Ctrl('t')], "beginning-of-line");
+        context.key_binding_table.unbind(BindingMode::Emacs, &[Key::Ctrl('a')]);
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Emacs::new(ed);
+        map.ed.insert_str_after_cursor("abc").unwrap();
+
+        simulate_keys!(map, [Key::Ctrl('a')]);
+        assert_eq!(map.ed.cursor(), 3);
+
+        simulate_keys!(map, [Key::Ctrl('t')]);
+        assert_eq!(map.ed.cursor(), 0);
+    }
+
+    #[test]
+    fn bind_key_sequence() {
+        let mut context = Context::new();
+        context.key_binding_table.bind(
+            BindingMode::Emacs,
+            &[Key::Ctrl('o'), Key::Char('a')],
+            "beginning-of-line",
+        );
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Emacs::new(ed);
+        map.ed.insert_str_after_cursor("abc").unwrap();
+
+        simulate_keys!(map, [Key::Ctrl('o'), Key::Char('a')]);
+        assert_eq!(map.ed.cursor(), 0);
+
+        // keys which break the sequence are handled as usual
+        simulate_keys!(map, [Key::Ctrl('o'), Key::Char('z'), Key::Ctrl('o'), Key::Ctrl('e')]);
+        assert_eq!(map.ed.cursor(), 4);
+        assert_eq!(String::from(map), "zabc");
+    }
+
+    #[test]
+    /// a bound key which starts a longer sequence runs once the sequence is broken
+    fn bound_key_sequence_prefix() {
+        let mut context = Context::new();
+        context.key_binding_table.bind(
+            BindingMode::Emacs,
+            &[Key::Ctrl('x'), Key::Ctrl('a')],
+            "beginning-of-line",
+        );
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Emacs::new(ed);
+
+        simulate_keys!(map, [Key::Char('a'), Key::Char('b')]);
+        simulate_keys!(map, [Key::Ctrl('x'), Key::Ctrl('a')]);
+        assert_eq!(map.ed.cursor(), 0);
+
+        // ctrl-x runs undo, then the next key is handled as usual
+        simulate_keys!(map, [Key::Ctrl('x'), Key::Char('c')]);
+        assert_eq!(String::from(map), "ac");
+    }
+
+    #[test]
+    fn kill_and_yank() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Emacs::new(ed);
+        map.ed.insert_str_after_cursor("hello world").unwrap();
+
+        simulate_keys!(map, [Key::Ctrl('a'), Key::Ctrl('k'), Key::Ctrl('y'), Key::Ctrl('y')]);
+        assert_eq!(String::from(map), "hello worldhello world");
+    }
+
+    #[test]
+    /// consecutive kills are yanked together
+    fn merge_kills() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Emacs::new(ed);
+        map.ed.insert_str_after_cursor("one two three").unwrap();
+
+        simulate_keys!(map, [Key::Ctrl('w'), Key::Ctrl('w'), Key::Ctrl('b'), Key::Ctrl('w')]);
+        assert_eq!(map.ed.context().kill_ring.len(), 2);
+        assert_eq!(String::from(map.ed.current_buffer().clone()), " ");
+
+        simulate_keys!(map, [Key::Ctrl('e'), Key::Ctrl('y'), Key::Alt('y')]);
+        assert_eq!(String::from(map), " two three");
+    }
+
+    #[test]
+    fn yank_pop() {
+        let mut context = Context::new();
+        context.kill_ring.push("a");
+        context.kill_ring.push("b");
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Emacs::new(ed);
+
+        simulate_keys!(map, [Key::Ctrl('y'), Key::Alt('y')]);
+        assert_eq!(String::from(map.ed.current_buffer().clone()), "a");
+
+        // yank-pop does nothing unless the last key yanked
+        simulate_keys!(map, [Key::Char('x'), Key::Alt('y')]);
+        assert_eq!(String::from(map.ed.current_buffer().clone()), "ax");
+
+        simulate_keys!(map, [Key::Ctrl('y'), Key::Alt('y'), Key::Alt('y'), Key::Ctrl('x')]);
+        assert_eq!(String::from(map), "axa");
+    }
+
+    #[test]
+    /// the kill ring is kept in the context between lines
+    fn yank_from_previous_line() {
+        let mut context = Context::new();
+        {
+            let out = Vec::new();
+            let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+            let mut map = Emacs::new(ed);
+            map.ed.insert_str_after_cursor("hello").unwrap();
+            simulate_keys!(map, [Key::Ctrl('u')]);
+        }
+
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Emacs::new(ed);
+        simulate_keys!(map, [Key::Ctrl('y')]);
+        assert_eq!(String::from(map), "hello");
+    }
+
+    #[test]
+    /// at the end of the line, alt-f accepts the next word of the autosuggestion
+    fn forward_word_accepts_autosuggestion() {
+        let mut context = Context::new();
+        context.history.push("git commit -m fix".into()).unwrap();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Emacs::new(ed);
+        map.ed.insert_str_after_cursor("git").unwrap();
+
+        simulate_keys!(map, [Key::Alt('f'), Key::Alt('f')]);
+        assert_eq!(String::from(map.ed.current_buffer().clone()), "git commit -m");
+
+        simulate_keys!(map, [Key::Ctrl('a'), Key::Alt('f')]);
+        assert_eq!(map.ed.cursor(), 3);
+        assert_eq!(String::from(map), "git commit -m");
+    }
+
+    #[test]
+    fn menu_complete() {
+        let mut context = Context::new();
+        context.completer = Some(Box::new(BasicCompleter::new(vec!["bar", "baz"])));
+        context.completion_options.menu_select = true;
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Emacs::new(ed);
+        map.ed.insert_str_after_cursor("b").unwrap();
+
+        // the first tab completes the common prefix, the second would list the completions, and
+        // the third shows the menu
+        simulate_keys!(map, [Key::Char('\t'), Key::Char('\t'), Key::Char('\t')]);
+        assert_eq!(String::from(map.ed.current_buffer().clone()), "bar");
+        simulate_keys!(map, [Key::Char('\t')]);
+        assert_eq!(String::from(map.ed.current_buffer().clone()), "baz");
+        simulate_keys!(map, [Key::Esc]);
+        assert_eq!(String::from(map.ed.current_buffer().clone()), "ba");
+
+        simulate_keys!(map, [Key::BackTab, Key::Char('\n')]);
+        assert!(!map.ed.is_menu_completing());
+        assert_eq!(String::from(map.ed.current_buffer().clone()), "baz");
+
+        // other keys accept the selected completion, then are handled as usual
+        simulate_keys!(map, [Key::Char(' '), Key::Char('b'), Key::BackTab]);
+        simulate_keys!(map, [Key::Left, Key::Char('!')]);
+        assert_eq!(String::from(map), "baz bar!");
+    }
+
+    #[test]
+    fn rebind_menu_keys() {
+        let mut context = Context::new();
+        context.completer = Some(Box::new(BasicCompleter::new(vec!["bar", "baz", "qux"])));
+        context.key_binding_table.bind(BindingMode::Menu, &[Key::Ctrl('j')], "menu-next");
+        context.key_binding_table.unbind(BindingMode::Menu, &[Key::Char('\t')]);
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Emacs::new(ed);
+
+        simulate_keys!(map, [Key::BackTab, Key::Ctrl('j')]);
+        assert!(map.ed.is_menu_completing());
+        assert_eq!(String::from(map.ed.current_buffer().clone()), "bar");
+
+        // tab is no longer bound in the menu, so it accepts the completion and completes again
+        simulate_keys!(map, [Key::Char('\t')]);
+        assert!(!map.ed.is_menu_completing());
+        assert_eq!(String::from(map), "bar");
+    }
 }
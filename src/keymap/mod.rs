@@ -1,51 +1,211 @@
 use std::io::{self, Write, ErrorKind};
+use std::collections::VecDeque;
+use std::mem;
 use termion::event::Key;
 use Editor;
 use event::*;
 
 pub trait KeyMap<'a, W: Write, T>: From<T> {
+    /// Handles a key which isn't bound to a command in `Context::key_binding_table`.
     fn handle_key_core(&mut self, key: Key) -> io::Result<()>;
     fn editor(&self) -> &Editor<'a, W>;
     fn editor_mut(&mut self) -> &mut Editor<'a, W>;
 
-    fn handle_key(&mut self, mut key: Key, handler: &mut EventHandler<W>) -> io::Result<bool> {
-        let mut done = false;
+    /// The bindings from `Context::key_binding_table` which apply in the current state.
+    fn binding_mode(&self) -> BindingMode;
 
-        handler(Event::new(self.editor_mut(), EventKind::BeforeKey(key)));
+    /// The keys typed so far of a key sequence which has not been completed yet.
+    fn pending_keys(&mut self) -> &mut Vec<Key>;
 
-        let is_empty = self.editor().current_buffer().is_empty();
+    /// Runs a command which is specific to this keymap.
+    ///
+    /// Returns `Ok(false)` if this keymap has no command called `name`, in which case the command
+    /// is run by `Editor::run_command()`.
+    fn run_keymap_command(&mut self, _: &str) -> io::Result<bool> {
+        Ok(false)
+    }
 
-        if key == Key::Ctrl('h') {
-            // XXX: Might need to change this when remappable keybindings are added.
-            key = Key::Backspace;
-        }
+    /// Handles a key pressed while a history search is in progress.
+    ///
+    /// Returns `Ok(true)` if the search used the key. Otherwise the search has ended and the key
+    /// is handled as usual. By default every key ends the search.
+    fn handle_key_search(&mut self, _: Key) -> io::Result<bool> {
+        try!(self.editor_mut().accept_search());
+        Ok(false)
+    }
+
+    /// Runs the named command. Returns `Ok(true)` if the line is done.
+    ///
+    /// Unknown commands are ignored.
+    fn run_command(&mut self, name: &str, handler: &mut EventHandler<W>) -> io::Result<bool> {
+        let is_empty = self.editor().current_buffer().is_empty();
 
-        match key {
-            Key::Ctrl('c') => {
+        match name {
+            "accept-line" => return self.editor_mut().handle_newline(),
+            "complete" => return self.editor_mut().complete(handler).map(|_| false),
+            "menu-complete" => {
+                return self.editor_mut().menu_complete(handler, false).map(|_| false)
+            }
+            "menu-complete-backward" => {
+                return self.editor_mut().menu_complete(handler, true).map(|_| false)
+            }
+            "interrupt" => {
                 try!(self.editor_mut().handle_newline());
                 return Err(io::Error::new(ErrorKind::Interrupted, "ctrl-c"));
             }
-            // if the current buffer is empty, treat ctrl-d as eof
-            Key::Ctrl('d') if is_empty => {
+            // if the current buffer is empty, treat these as eof
+            "end-of-file" | "delete-char-or-eof" if is_empty => {
                 try!(self.editor_mut().handle_newline());
                 return Err(io::Error::new(ErrorKind::UnexpectedEof, "ctrl-d"));
             }
-            Key::Char('\t') => try!(self.editor_mut().complete(handler)),
-            Key::Char('\n') => {
-                done = try!(self.editor_mut().handle_newline());
-            }
-            Key::Ctrl('f') if self.editor().is_currently_showing_autosuggestion() => {
-                try!(self.editor_mut().accept_autosuggestion());
+            "end-of-file" => {}
+            "delete-char-or-eof" => try!(self.editor_mut().delete_after_cursor()),
+            _ => {
+                if !try!(self.run_keymap_command(name)) {
+                    try!(self.editor_mut().run_command(name));
+                }
             }
-            Key::Right if self.editor().is_currently_showing_autosuggestion() &&
-                          self.editor().cursor_is_at_end_of_line() => {
-                try!(self.editor_mut().accept_autosuggestion());
+        }
+
+        self.editor_mut().skip_completions_hint();
+        Ok(false)
+    }
+
+    /// Inserts pasted text literally, without running any key bindings. Line endings are
+    /// converted to `'\n'`.
+    fn handle_paste(&mut self, text: &str, handler: &mut EventHandler<W>) -> io::Result<()> {
+        let mut text = text.replace("\r\n", "\n").replace('\r', "\n");
+
+        handler(Event::new(self.editor_mut(), EventKind::Paste(&mut text)));
+
+        self.editor_mut().start_key();
+        self.editor_mut().paste(&text)
+    }
+
+    /// Handles a key pressed while the completion menu is shown, by running the command it is
+    /// bound to in `BindingMode::Menu`.
+    ///
+    /// Returns `Ok(true)` if the menu used the key. Otherwise the selected completion has been
+    /// accepted and the key is handled as usual.
+    fn handle_key_menu(&mut self, key: Key) -> io::Result<bool> {
+        let command = self.editor_mut()
+            .context()
+            .key_binding_table
+            .get(BindingMode::Menu, &[key])
+            .map(|c| c.to_owned());
+
+        if let Some(command) = command {
+            if try!(self.editor_mut().run_command(&command)) {
+                return Ok(true);
             }
-            _ => {
-                try!(self.handle_key_core(key));
-                self.editor_mut().skip_completions_hint();
+        }
+
+        try!(self.editor_mut().accept_menu_completion());
+        Ok(false)
+    }
+
+    /// Handles a key which termion doesn't know, such as ctrl-right, by running the command its
+    /// `unsupported_key_sequence()` is bound to. Nothing happens if the sequence isn't bound.
+    ///
+    /// Each key of the sequence is sent in a `BeforeKey` and `AfterKey` event.
+    fn handle_unsupported_keys(
+        &mut self,
+        keys: &[Key],
+        handler: &mut EventHandler<W>,
+    ) -> io::Result<bool> {
+        let mut done = false;
+
+        for &key in keys {
+            handler(Event::new(self.editor_mut(), EventKind::BeforeKey(key)));
+        }
+
+        self.editor_mut().start_key();
+
+        let mode = self.binding_mode();
+        let command = self.editor_mut()
+            .context()
+            .key_binding_table
+            .get(mode, keys)
+            .map(|c| c.to_owned());
+        if let Some(command) = command {
+            done = try!(self.run_command(&command, handler));
+        }
+
+        for &key in keys {
+            handler(Event::new(self.editor_mut(), EventKind::AfterKey(key)));
+        }
+
+        try!(self.editor_mut().flush());
+
+        Ok(done)
+    }
+
+    fn handle_key(&mut self, key: Key, handler: &mut EventHandler<W>) -> io::Result<bool> {
+        let mut done = false;
+
+        handler(Event::new(self.editor_mut(), EventKind::BeforeKey(key)));
+
+        self.editor_mut().start_key();
+
+        let handled = (self.editor().is_menu_completing() && try!(self.handle_key_menu(key))) ||
+            (self.editor().is_searching() && try!(self.handle_key_search(key)));
+        if !handled {
+            let mode = self.binding_mode();
+            let mut queue = VecDeque::new();
+            queue.push_back(key);
+
+            while let Some(key) = queue.pop_front() {
+                if done {
+                    break;
+                }
+
+                let mut keys = self.pending_keys().clone();
+                keys.push(key);
+
+                let binding = self.editor_mut().context().key_binding_table.lookup(mode, &keys);
+                match binding {
+                    Binding::Command(name) => {
+                        self.pending_keys().clear();
+                        done = try!(self.run_command(&name, handler));
+                    }
+                    Binding::Prefix => *self.pending_keys() = keys,
+                    Binding::Unbound if keys.len() == 1 => {
+                        try!(self.handle_key_core(key));
+                        self.editor_mut().skip_completions_hint();
+                    }
+                    Binding::Unbound => {
+                        // `key` does not continue the pending sequence. The longest bound start
+                        // of the pending keys runs its command, or if nothing is bound, the first
+                        // key is handled on its own. The remaining keys are then looked up again.
+                        let pending = mem::replace(self.pending_keys(), Vec::new());
+                        let bound = (1..pending.len() + 1).rev().filter_map(|n| {
+                            self.editor_mut()
+                                .context()
+                                .key_binding_table
+                                .get(mode, &pending[..n])
+                                .map(|name| (n, name.to_owned()))
+                        }).next();
+
+                        let handled = match bound {
+                            Some((n, name)) => {
+                                done = try!(self.run_command(&name, handler));
+                                n
+                            }
+                            None => {
+                                try!(self.handle_key_core(pending[0]));
+                                self.editor_mut().skip_completions_hint();
+                                1
+                            }
+                        };
+
+                        queue.push_front(key);
+                        for &k in pending[handled..].iter().rev() {
+                            queue.push_front(k);
+                        }
+                    }
+                }
             }
-        };
+        }
 
         handler(Event::new(self.editor_mut(), EventKind::AfterKey(key)));
 
@@ -55,6 +215,9 @@ pub trait KeyMap<'a, W: Write, T>: From<T> {
     }
 }
 
+pub mod bindings;
+pub use bindings::{unsupported_key_sequence, Binding, BindingMode, KeyBindingTable};
+
 pub mod vi;
 pub use vi::Vi;
 
@@ -70,12 +233,14 @@ mod tests {
 
     struct TestKeyMap<'a, W: Write> {
         ed: Editor<'a, W>,
+        pending_keys: Vec<Key>,
     }
 
     impl<'a, W: Write> TestKeyMap<'a, W> {
         pub fn new(ed: Editor<'a, W>) -> Self {
             TestKeyMap {
                 ed: ed,
+                pending_keys: Vec::new(),
             }
         }
     }
@@ -85,6 +250,14 @@ mod tests {
             Ok(())
         }
 
+        fn binding_mode(&self) -> BindingMode {
+            BindingMode::Emacs
+        }
+
+        fn pending_keys(&mut self) -> &mut Vec<Key> {
+            &mut self.pending_keys
+        }
+
         fn editor_mut(&mut self) ->  &mut Editor<'a, W> {
             &mut self.ed
         }
@@ -132,4 +305,24 @@ mod tests {
         assert_eq!(res.is_err(), true);
         assert_eq!(res.err().unwrap().kind(), ErrorKind::Interrupted);
     }
+
+    #[test]
+    /// pasted text is inserted as one change, and can be changed by the event handler
+    fn paste() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = TestKeyMap::new(ed);
+        map.ed.insert_str_after_cursor("ab").unwrap();
+        map.ed.move_cursor_left(1).unwrap();
+
+        map.handle_paste("x\r\ny\u{7}\rz", &mut |e| if let EventKind::Paste(text) = e.kind {
+            text.retain(|c| c != '\u{7}');
+        }).unwrap();
+        assert_eq!(map.ed.cursor(), 6);
+        assert_eq!(String::from(map.ed.current_buffer().clone()), "ax\ny\nzb");
+
+        map.ed.undo().unwrap();
+        assert_eq!(String::from(map.ed.current_buffer().clone()), "ab");
+    }
 }
@@ -3,7 +3,9 @@ use std::io::{self, Write};
 use termion::event::Key;
 
 use KeyMap;
+use BindingMode;
 use Editor;
+use SearchDirection;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum CharMovement {
@@ -28,9 +30,23 @@ enum Mode {
     Normal,
     Replace,
     Delete(usize),
+    Yank(usize),
+    Register,
     MoveToChar(CharMovement),
     G,
     Tilde,
+    Visual { linewise: bool },
+    TextObject { inner: bool },
+}
+
+impl Mode {
+    /// In these modes the cursor is on a character, so it can't be after the end of the line.
+    fn is_on_char(self) -> bool {
+        match self {
+            Mode::Normal | Mode::Visual { .. } => true,
+            _ => false,
+        }
+    }
 }
 
 struct ModeStack(Vec<Mode>);
@@ -290,6 +306,168 @@ fn find_char_rev(buf: &::buffer::Buffer, start: usize, ch: char, count: usize) -
         .map(|(i, _)| i)
 }
 
+/// The range of the word under the cursor, for the `iw`, `aw`, `iW` and `aW` text objects.
+///
+/// Around a word includes the whitespace after it, or before it if there is none after. Around
+/// whitespace includes the word after it.
+fn word_object(
+    buf: &::buffer::Buffer,
+    cursor: usize,
+    inner: bool,
+    move_mode: ViMoveMode,
+) -> Option<(usize, usize)> {
+    let chars: Vec<char> = buf.chars().cloned().collect();
+    if chars.is_empty() {
+        return None;
+    }
+
+    // characters of the same class next to each other form a word
+    let class = |c: char| if c.is_whitespace() {
+        0
+    } else if move_mode == ViMoveMode::Whitespace || is_vi_keyword(c) {
+        1
+    } else {
+        2
+    };
+    let run = |pos: usize| {
+        let c = class(chars[pos]);
+        let mut start = pos;
+        while start > 0 && class(chars[start - 1]) == c {
+            start -= 1;
+        }
+        let mut end = pos + 1;
+        while end < chars.len() && class(chars[end]) == c {
+            end += 1;
+        }
+        (start, end)
+    };
+
+    let cursor = cmp::min(cursor, chars.len() - 1);
+    let (start, end) = run(cursor);
+    let is_space = |pos: usize| class(chars[pos]) == 0;
+
+    Some(if inner {
+        (start, end)
+    } else if end < chars.len() && (is_space(cursor) || is_space(end)) {
+        (start, run(end).1)
+    } else if start > 0 && !is_space(cursor) && is_space(start - 1) {
+        (run(start - 1).0, end)
+    } else {
+        (start, end)
+    })
+}
+
+/// The range of the quoted string around or after the cursor on the current line, for the `i"`,
+/// `a"` and similar text objects. Quotes escaped with a backslash are skipped.
+///
+/// Around a quoted string includes the whitespace after it, or before it if there is none after.
+fn quote_object(
+    buf: &::buffer::Buffer,
+    cursor: usize,
+    quote: char,
+    inner: bool,
+) -> Option<(usize, usize)> {
+    let chars: Vec<char> = buf.chars().cloned().collect();
+    let line_start = chars[..cmp::min(cursor, chars.len())]
+        .iter()
+        .rposition(|&c| c == '\n')
+        .map_or(0, |i| i + 1);
+    let line_end = chars[line_start..]
+        .iter()
+        .position(|&c| c == '\n')
+        .map_or(chars.len(), |i| line_start + i);
+
+    let mut quotes = Vec::new();
+    let mut escaped = false;
+    for (i, &c) in chars.iter().enumerate().take(line_end).skip(line_start) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            _ if c == quote => quotes.push(i),
+            _ => {}
+        }
+    }
+
+    let (open, close) = quotes
+        .chunks(2)
+        .filter(|pair| pair.len() == 2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|&(_, close)| cursor <= close)?;
+
+    if inner {
+        return Some((open + 1, close));
+    }
+
+    let is_space = |c: char| c.is_whitespace() && c != '\n';
+    let mut start = open;
+    let mut end = close + 1;
+    while end < line_end && is_space(chars[end]) {
+        end += 1;
+    }
+    if end == close + 1 {
+        while start > line_start && is_space(chars[start - 1]) {
+            start -= 1;
+        }
+    }
+    Some((start, end))
+}
+
+/// The range of the innermost pair of brackets around the cursor, for the `i(`, `a(` and similar
+/// text objects.
+fn bracket_object(
+    buf: &::buffer::Buffer,
+    cursor: usize,
+    open: char,
+    close: char,
+    inner: bool,
+) -> Option<(usize, usize)> {
+    let chars: Vec<char> = buf.chars().cloned().collect();
+    if chars.is_empty() {
+        return None;
+    }
+    let cursor = cmp::min(cursor, chars.len() - 1);
+
+    let open_pos = if chars[cursor] == open {
+        cursor
+    } else {
+        let mut depth = 0;
+        let mut found = None;
+        for (i, &c) in chars[..cursor].iter().enumerate().rev() {
+            if c == close {
+                depth += 1;
+            } else if c == open {
+                if depth == 0 {
+                    found = Some(i);
+                    break;
+                }
+                depth -= 1;
+            }
+        }
+        found?
+    };
+
+    let mut depth = 0;
+    let mut close_pos = None;
+    for (i, &c) in chars.iter().enumerate().skip(open_pos + 1) {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            if depth == 0 {
+                close_pos = Some(i);
+                break;
+            }
+            depth -= 1;
+        }
+    }
+    let close_pos = close_pos?;
+
+    if inner {
+        Some((open_pos + 1, close_pos))
+    } else {
+        Some((open_pos, close_pos + 1))
+    }
+}
+
 /// Vi keybindings for `Editor`.
 ///
 /// ```
@@ -309,6 +487,9 @@ pub struct Vi<'a, W: Write> {
     last_count: u32,
     movement_reset: bool,
     last_char_movement: Option<(char, CharMovement)>,
+    last_search_direction: SearchDirection,
+    pending_keys: Vec<Key>,
+    register: Option<char>,
 }
 
 impl<'a, W: Write> Vi<'a, W> {
@@ -329,6 +510,9 @@ impl<'a, W: Write> Vi<'a, W> {
             last_count: 0,
             movement_reset: false,
             last_char_movement: None,
+            last_search_direction: SearchDirection::Backward,
+            pending_keys: Vec::new(),
+            register: None,
         }
     }
 
@@ -349,7 +533,7 @@ impl<'a, W: Write> Vi<'a, W> {
     fn set_mode_preserve_last(&mut self, mode: Mode) {
         use self::Mode::*;
 
-        self.ed.no_eol = mode == Normal;
+        self.ed.no_eol = mode.is_on_char();
         self.movement_reset = mode != Insert;
         self.mode_stack.push(mode);
 
@@ -362,21 +546,30 @@ impl<'a, W: Write> Vi<'a, W> {
         use self::Mode::*;
         use self::MoveType::*;
 
+        // in visual mode, movements only change the selection
+        if let Visual { .. } = self.mode() {
+            self.count = 0;
+            return Ok(());
+        }
+
         let original_mode = self.mode_stack.pop();
         let last_mode = {
-            // after popping, if mode is delete or change, pop that too. This is used for movements
-            // with sub commands like 't' (MoveToChar) and 'g' (G).
+            // after popping, if mode is delete, change or yank, pop that too. This is used for
+            // movements with sub commands like 't' (MoveToChar) and 'g' (G).
             match self.mode() {
-                Delete(_) => self.mode_stack.pop(),
+                Delete(_) | Yank(_) => self.mode_stack.pop(),
                 _ => original_mode,
             }
         };
 
-        self.ed.no_eol = self.mode() == Mode::Normal;
+        self.ed.no_eol = self.mode().is_on_char();
         self.movement_reset = self.mode() != Mode::Insert;
 
         match last_mode {
             Delete(start_pos) => {
+                let (start, end) = self.movement_range(start_pos, move_type);
+                self.yank_range(start, end);
+
                 // perform the delete operation
                 match move_type {
                     Exclusive => try!(self.ed.delete_until(start_pos)),
@@ -392,6 +585,16 @@ impl<'a, W: Write> Vi<'a, W> {
                 self.count = 0;
                 self.secondary_count = 0;
             }
+            Yank(start_pos) => {
+                let (start, end) = self.movement_range(start_pos, move_type);
+                self.yank_range(start, end);
+
+                // the cursor ends up at the start of the yanked text
+                try!(self.ed.move_cursor_to(start));
+
+                self.count = 0;
+                self.secondary_count = 0;
+            }
             _ => {}
         };
 
@@ -403,11 +606,66 @@ impl<'a, W: Write> Vi<'a, W> {
         Ok(())
     }
 
+    /// The range of text between `start_pos` and the cursor, as used by a delete or yank with the
+    /// given movement type.
+    fn movement_range(&self, start_pos: usize, move_type: MoveType) -> (usize, usize) {
+        let cursor = self.ed.cursor();
+        let end = match move_type {
+            MoveType::Exclusive => cmp::max(cursor, start_pos),
+            MoveType::Inclusive => cmp::max(cursor, start_pos) + 1,
+        };
+        (cmp::min(cursor, start_pos), cmp::min(end, self.ed.current_buffer().num_chars()))
+    }
+
+    /// Saves the text between `start` and `end` in the register selected with `"`, and in the
+    /// unnamed register.
+    fn yank_range(&mut self, start: usize, end: usize) {
+        let text = self.ed.current_buffer().range(start, end);
+        let register = self.register.take();
+        let registers = &mut self.ed.context().vi_registers;
+
+        let text = match register {
+            // uppercase registers append to the lowercase ones
+            Some(c) if c.is_uppercase() => {
+                let reg = registers.entry(c.to_ascii_lowercase()).or_insert_with(String::new);
+                reg.push_str(&text);
+                reg.clone()
+            }
+            Some(c) if c != '"' => {
+                registers.insert(c, text.clone());
+                text
+            }
+            _ => text,
+        };
+        registers.insert('"', text);
+    }
+
+    /// Inserts the contents of the selected register `count` times, after the cursor if `after`
+    /// is true. The cursor ends up on the last inserted character.
+    fn put(&mut self, after: bool, count: usize) -> io::Result<()> {
+        let register = self.register.take().map_or('"', |c| c.to_ascii_lowercase());
+        let text: Vec<char> = match self.ed.context().vi_registers.get(&register) {
+            Some(text) => text.chars().collect(),
+            None => return Ok(()),
+        };
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        let text: Vec<char> = text.iter().cloned().cycle().take(text.len() * count).collect();
+        let pos = match self.ed.cursor() {
+            c if after => cmp::min(c + 1, self.ed.current_buffer().num_chars()),
+            c => c,
+        };
+        self.ed.current_buffer_mut().insert(pos, &text);
+        self.ed.move_cursor_to(pos + text.len() - 1)
+    }
+
     fn pop_mode(&mut self) {
         use self::Mode::*;
 
         let last_mode = self.mode_stack.pop();
-        self.ed.no_eol = self.mode() == Normal;
+        self.ed.no_eol = self.mode().is_on_char();
         self.movement_reset = self.mode() != Insert;
 
         if last_mode == Insert || last_mode == Tilde {
@@ -419,11 +677,44 @@ impl<'a, W: Write> Vi<'a, W> {
         }
     }
 
+    /// Leave visual mode, clearing the selection.
+    fn exit_visual_mode(&mut self) -> io::Result<()> {
+        self.pop_mode();
+        self.count = 0;
+        self.ed.clear_selection()
+    }
+
+    /// Replaces every selected character with the result of `f`, then leaves visual mode.
+    fn map_selection<F: Fn(char) -> String>(&mut self, f: F) -> io::Result<()> {
+        let (start, end) = match self.ed.selection() {
+            Some(range) => range,
+            None => return Ok(()),
+        };
+
+        let text: Vec<char> = self.ed
+            .current_buffer()
+            .range(start, end)
+            .chars()
+            .flat_map(|c| f(c).chars().collect::<Vec<_>>())
+            .collect();
+
+        try!(self.exit_visual_mode());
+        {
+            let buf = self.ed.current_buffer_mut();
+            buf.start_undo_group();
+            buf.remove(start, end);
+            buf.insert(start, &text);
+            buf.end_undo_group();
+        }
+        self.ed.move_cursor_to(start)
+    }
+
     /// Return to normal mode.
     fn normal_mode_abort(&mut self) {
         self.mode_stack.clear();
         self.ed.no_eol = true;
         self.count = 0;
+        self.register = None;
     }
 
     /// When doing a move, 0 should behave the same as 1 as far as the count goes.
@@ -563,6 +854,7 @@ impl<'a, W: Write> Vi<'a, W> {
         match key {
             Key::Esc => {
                 self.count = 0;
+                self.register = None;
                 Ok(())
             }
             Key::Char('i') => {
@@ -588,7 +880,9 @@ impl<'a, W: Write> Vi<'a, W> {
             Key::Char('s') => {
                 self.last_insert = Some(key);
                 self.set_mode(Insert);
-                let pos = self.ed.cursor() + self.move_count_right();
+                let start = self.ed.cursor();
+                let pos = start + self.move_count_right();
+                self.yank_range(start, pos);
                 try!(self.ed.delete_until(pos));
                 self.last_count = self.count;
                 self.count = 0;
@@ -619,6 +913,38 @@ impl<'a, W: Write> Vi<'a, W> {
                 self.count = 0;
                 Ok(())
             }
+            Key::Char('y') => {
+                let start_pos = self.ed.cursor();
+                self.set_mode(Mode::Yank(start_pos));
+                self.secondary_count = self.count;
+                self.count = 0;
+                Ok(())
+            }
+            Key::Char('p') | Key::Char('P') => {
+                // update the last command state, including the register so that it is used again
+                self.last_insert = None;
+                self.last_command.clear();
+                if let Some(c) = self.register {
+                    self.last_command.push(Key::Char('"'));
+                    self.last_command.push(Key::Char(c));
+                }
+                self.last_command.push(key);
+                self.last_count = self.count;
+
+                let count = self.move_count();
+                self.count = 0;
+                self.put(key == Key::Char('p'), count)
+            }
+            Key::Char('"') => {
+                self.set_mode(Mode::Register);
+                Ok(())
+            }
+            Key::Char('v') | Key::Char('V') => {
+                let linewise = key == Key::Char('V');
+                self.count = 0;
+                self.set_mode(Visual { linewise: linewise });
+                self.ed.start_selection(linewise)
+            }
             Key::Char('D') => {
                 // update the last command state
                 self.last_insert = None;
@@ -627,6 +953,8 @@ impl<'a, W: Write> Vi<'a, W> {
                 self.count = 0;
                 self.last_count = 0;
 
+                let (start, end) = (self.ed.cursor(), self.ed.current_buffer().num_chars());
+                self.yank_range(start, end);
                 self.ed.delete_all_after_cursor()
             }
             Key::Char('C') => {
@@ -638,6 +966,8 @@ impl<'a, W: Write> Vi<'a, W> {
                 self.last_count = 0;
 
                 self.set_mode_preserve_last(Insert);
+                let (start, end) = (self.ed.cursor(), self.ed.current_buffer().num_chars());
+                self.yank_range(start, end);
                 self.ed.delete_all_after_cursor()
             }
             Key::Char('.') => {
@@ -746,7 +1076,9 @@ impl<'a, W: Write> Vi<'a, W> {
                 self.last_command.push(key);
                 self.last_count = self.count;
 
-                let pos = self.ed.cursor() + self.move_count_right();
+                let start = self.ed.cursor();
+                let pos = start + self.move_count_right();
+                self.yank_range(start, pos);
                 try!(self.ed.delete_until(pos));
                 self.count = 0;
                 Ok(())
@@ -780,6 +1112,34 @@ impl<'a, W: Write> Vi<'a, W> {
                 self.pop_mode();
                 Ok(())
             }
+            // like in ksh, history is searched as if the newest entry is at the top
+            Key::Char('/') => {
+                self.count = 0;
+                self.last_search_direction = SearchDirection::Backward;
+                self.ed.start_search_prompt(SearchDirection::Backward, "/")
+            }
+            Key::Char('?') => {
+                self.count = 0;
+                self.last_search_direction = SearchDirection::Forward;
+                self.ed.start_search_prompt(SearchDirection::Forward, "?")
+            }
+            Key::Char('n') | Key::Char('N') => {
+                let direction = match (key, self.last_search_direction) {
+                    (Key::Char('n'), dir) => dir,
+                    (_, SearchDirection::Forward) => SearchDirection::Backward,
+                    (_, SearchDirection::Backward) => SearchDirection::Forward,
+                };
+
+                let count = self.move_count();
+                self.count = 0;
+                for _ in 0..count {
+                    let found = try!(self.ed.search_again(direction));
+                    if !found {
+                        break;
+                    }
+                }
+                Ok(())
+            }
             Key::Char('u') => {
                 let count = self.move_count();
                 self.count = 0;
@@ -807,6 +1167,18 @@ impl<'a, W: Write> Vi<'a, W> {
     }
 
     fn handle_key_replace(&mut self, key: Key) -> io::Result<()> {
+        // in visual mode, every selected character is replaced
+        if self.ed.selection().is_some() {
+            self.pop_mode();
+            return match key {
+                Key::Char(c) => self.map_selection(|old| match old {
+                    '\n' => old.to_string(),
+                    _ => c.to_string(),
+                }),
+                _ => self.exit_visual_mode(),
+            };
+        }
+
         match key {
             Key::Char(c) => {
                 // make sure there are enough chars to replace
@@ -842,6 +1214,11 @@ impl<'a, W: Write> Vi<'a, W> {
     }
 
     fn handle_key_delete_or_change(&mut self, key: Key) -> io::Result<()> {
+        let yanking = match self.mode() {
+            Mode::Yank(_) => true,
+            _ => false,
+        };
+
         match (key, self.current_insert) {
             // check if this is a movement key
             (key, _) if is_movement_key(key) | (key == Key::Char('0') && self.count == 0) => {
@@ -867,7 +1244,24 @@ impl<'a, W: Write> Vi<'a, W> {
             (Key::Char('0'...'9'), _) => {
                 self.handle_key_normal(key)
             }
-            (Key::Char('c'), Some(Key::Char('c'))) | (Key::Char('d'), None) => {
+            // text objects
+            (Key::Char('i'), _) | (Key::Char('a'), _) => {
+                self.current_command.push(key);
+                self.set_mode(Mode::TextObject { inner: key == Key::Char('i') });
+                Ok(())
+            }
+            (Key::Char('y'), _) if yanking => {
+                // yank the whole line
+                self.count = 0;
+                self.secondary_count = 0;
+                let end = self.ed.current_buffer().num_chars();
+                self.yank_range(0, end);
+
+                // return to the previous mode
+                self.pop_mode();
+                Ok(())
+            }
+            (Key::Char('c'), Some(Key::Char('c'))) | (Key::Char('d'), None) if !yanking => {
                 // updating the last command buffer doesn't really make sense in this context.
                 // Repeating 'dd' will simply erase and already erased line. Any other commands
                 // will then become the new last command and the user will need to press 'dd' again
@@ -878,6 +1272,8 @@ impl<'a, W: Write> Vi<'a, W> {
                 // delete the whole line
                 self.count = 0;
                 self.secondary_count = 0;
+                let end = self.ed.current_buffer().num_chars();
+                self.yank_range(0, end);
                 try!(self.ed.move_cursor_to_start_of_line());
                 try!(self.ed.delete_all_after_cursor());
 
@@ -893,6 +1289,141 @@ impl<'a, W: Write> Vi<'a, W> {
         }
     }
 
+    fn handle_key_visual(&mut self, key: Key, linewise: bool) -> io::Result<()> {
+        let (start, end) = match self.ed.selection() {
+            Some(range) => range,
+            None => return self.exit_visual_mode(),
+        };
+
+        match key {
+            Key::Esc => self.exit_visual_mode(),
+            // switch between selecting characters and lines, or leave visual mode
+            Key::Char('v') | Key::Char('V') => {
+                let new_linewise = key == Key::Char('V');
+                if new_linewise == linewise {
+                    self.exit_visual_mode()
+                } else {
+                    self.mode_stack.pop();
+                    self.mode_stack.push(Mode::Visual { linewise: new_linewise });
+                    self.ed.start_selection(new_linewise)
+                }
+            }
+            Key::Char('d') | Key::Char('x') | Key::Delete => {
+                self.yank_range(start, end);
+                try!(self.exit_visual_mode());
+                try!(self.ed.move_cursor_to(start));
+                self.ed.delete_until(end)
+            }
+            Key::Char('c') | Key::Char('s') => {
+                self.yank_range(start, end);
+                try!(self.exit_visual_mode());
+                self.last_insert = Some(Key::Char('i'));
+                self.set_mode(Mode::Insert);
+                try!(self.ed.move_cursor_to(start));
+                self.ed.delete_until(end)
+            }
+            Key::Char('y') => {
+                self.yank_range(start, end);
+                try!(self.exit_visual_mode());
+                self.ed.move_cursor_to(start)
+            }
+            Key::Char('~') => self.map_selection(|c| if c.is_lowercase() {
+                c.to_uppercase().collect()
+            } else {
+                c.to_lowercase().collect()
+            }),
+            Key::Char('u') => self.map_selection(|c| c.to_lowercase().collect()),
+            Key::Char('U') => self.map_selection(|c| c.to_uppercase().collect()),
+            Key::Char('r') => {
+                self.set_mode(Mode::Replace);
+                Ok(())
+            }
+            Key::Char('"') => {
+                self.set_mode(Mode::Register);
+                Ok(())
+            }
+            Key::Char('i') | Key::Char('a') => {
+                self.set_mode(Mode::TextObject { inner: key == Key::Char('i') });
+                Ok(())
+            }
+            // movements change the selection
+            key if is_movement_key(key) || key == Key::Char('0') => self.handle_key_normal(key),
+            Key::Char('1'...'9') => self.handle_key_normal(key),
+            _ => Ok(()),
+        }
+    }
+
+    /// Selects a text object in visual mode, or applies the pending operator to it.
+    fn handle_key_text_object(&mut self, key: Key, inner: bool) -> io::Result<()> {
+        let range = {
+            let buf = self.ed.current_buffer();
+            let cursor = self.ed.cursor();
+            match key {
+                Key::Char('w') => word_object(buf, cursor, inner, ViMoveMode::Keyword),
+                Key::Char('W') => word_object(buf, cursor, inner, ViMoveMode::Whitespace),
+                Key::Char(c @ '"') | Key::Char(c @ '\'') | Key::Char(c @ '`') => {
+                    quote_object(buf, cursor, c, inner)
+                }
+                Key::Char('(') | Key::Char(')') | Key::Char('b') => {
+                    bracket_object(buf, cursor, '(', ')', inner)
+                }
+                Key::Char('[') | Key::Char(']') => bracket_object(buf, cursor, '[', ']', inner),
+                Key::Char('{') | Key::Char('}') | Key::Char('B') => {
+                    bracket_object(buf, cursor, '{', '}', inner)
+                }
+                Key::Char('<') | Key::Char('>') => bracket_object(buf, cursor, '<', '>', inner),
+                _ => None,
+            }
+        };
+
+        self.mode_stack.pop();
+        let visual = match self.mode() {
+            Mode::Visual { .. } => true,
+            _ => false,
+        };
+
+        let (start, end) = match range {
+            Some(range) => range,
+            // no such object, stay in visual mode or abort the operator
+            None if visual => return Ok(()),
+            None => {
+                self.normal_mode_abort();
+                return Ok(());
+            }
+        };
+
+        if visual {
+            self.mode_stack.pop();
+            self.mode_stack.push(Mode::Visual { linewise: false });
+            try!(self.ed.clear_selection());
+            try!(self.ed.move_cursor_to(start));
+            try!(self.ed.start_selection(false));
+            return self.ed.move_cursor_to(cmp::max(end, start + 1) - 1);
+        }
+
+        // the operator applies from the start of the object to its end, as if it were a movement
+        let operator = match self.mode_stack.pop() {
+            Mode::Yank(_) => Mode::Yank(start),
+            _ => Mode::Delete(start),
+        };
+        self.mode_stack.push(operator);
+        self.current_command.push(key);
+        try!(self.ed.move_cursor_to(end));
+        self.pop_mode_after_movement(MoveType::Exclusive)
+    }
+
+    fn handle_key_register(&mut self, key: Key) -> io::Result<()> {
+        match key {
+            Key::Char(c) if c.is_ascii_alphabetic() || c == '"' => {
+                self.register = Some(c);
+                self.pop_mode();
+            }
+            // not a register, back to normal mode
+            _ => self.normal_mode_abort(),
+        }
+        Ok(())
+    }
+
     fn handle_key_move_to_char(&mut self, key: Key, movement: CharMovement) -> io::Result<()> {
         use self::CharMovement::*;
         use self::MoveType::*;
@@ -996,18 +1527,109 @@ impl<'a, W: Write> Vi<'a, W> {
 }
 
 impl<'a, W: Write> KeyMap<'a, W, Vi<'a, W>> for Vi<'a, W> {
+    fn handle_key_search(&mut self, key: Key) -> io::Result<bool> {
+        match key {
+            Key::Char('\n') => {
+                try!(self.ed.submit_search());
+            }
+            Key::Char(c) => try!(self.ed.search_insert_char(c)),
+            // backspace on an empty query cancels the search
+            Key::Backspace | Key::Ctrl('h') if self.ed.search_query() == Some("") => {
+                try!(self.ed.abort_search())
+            }
+            Key::Backspace | Key::Ctrl('h') => try!(self.ed.search_delete_before_cursor()),
+            Key::Esc => try!(self.ed.abort_search()),
+            // ctrl-c cancels the whole line
+            Key::Ctrl('c') => {
+                try!(self.ed.abort_search());
+                return Ok(false);
+            }
+            _ => {}
+        }
+
+        Ok(true)
+    }
+
     fn handle_key_core(&mut self, key: Key) -> io::Result<()> {
         match self.mode() {
             Mode::Normal => self.handle_key_normal(key),
             Mode::Insert => self.handle_key_insert(key),
             Mode::Replace => self.handle_key_replace(key),
-            Mode::Delete(_) => self.handle_key_delete_or_change(key),
+            Mode::Delete(_) | Mode::Yank(_) => self.handle_key_delete_or_change(key),
+            Mode::Register => self.handle_key_register(key),
+            Mode::Visual { linewise } => self.handle_key_visual(key, linewise),
+            Mode::TextObject { inner } => self.handle_key_text_object(key, inner),
             Mode::MoveToChar(movement) => self.handle_key_move_to_char(key, movement),
             Mode::G => self.handle_key_g(key),
             Mode::Tilde => unreachable!(),
         }
     }
 
+    fn binding_mode(&self) -> BindingMode {
+        match self.mode() {
+            Mode::Insert => BindingMode::ViInsert,
+            _ => BindingMode::ViNormal,
+        }
+    }
+
+    fn pending_keys(&mut self) -> &mut Vec<Key> {
+        &mut self.pending_keys
+    }
+
+    fn run_keymap_command(&mut self, name: &str) -> io::Result<bool> {
+        // Commands which have a vi key of their own are run as that key, so that counts, undo
+        // groups and repeating with '.' work as usual.
+        let key = match name {
+            "accept-autosuggestion" => {
+                if self.ed.is_currently_showing_autosuggestion() {
+                    try!(self.ed.accept_autosuggestion());
+                }
+                return Ok(true);
+            }
+            "forward-char" => {
+                if self.ed.is_currently_showing_autosuggestion() &&
+                    self.ed.cursor_is_at_end_of_line()
+                {
+                    try!(self.ed.accept_autosuggestion());
+                    return Ok(true);
+                }
+                Key::Right
+            }
+            "forward-word" => {
+                if self.ed.is_currently_showing_autosuggestion() &&
+                    self.ed.cursor_is_at_end_of_line()
+                {
+                    try!(self.ed.accept_autosuggestion_word());
+                    return Ok(true);
+                }
+                if self.mode() == Mode::Insert {
+                    try!(move_word(&mut self.ed, 1));
+                    return Ok(true);
+                }
+                Key::Char('w')
+            }
+            "backward-word" => {
+                if self.mode() == Mode::Insert {
+                    try!(move_word_back(&mut self.ed, 1));
+                    return Ok(true);
+                }
+                Key::Char('b')
+            }
+            "backward-char" => Key::Left,
+            "backward-delete-char" => Key::Backspace,
+            "delete-char" => Key::Delete,
+            "previous-history" => Key::Up,
+            "next-history" => Key::Down,
+            "beginning-of-line" => Key::Home,
+            "end-of-line" => Key::End,
+            "vi-movement-mode" if self.mode() == Mode::Insert => Key::Esc,
+            _ => return Ok(false),
+        };
+
+        try!(self.handle_key_core(key));
+        Ok(true)
+    }
+
     fn editor_mut(&mut self) ->  &mut Editor<'a, W> {
         &mut self.ed
     }
@@ -3714,4 +4336,747 @@ mod tests {
         assert_eq!(res.is_ok(), true);
         assert_eq!(map.ed.current_buffer().to_string(), "not empt".to_string());
     }
+
+    #[test]
+    /// search history with '/' and repeat with 'n' and 'N'
+    fn search_history() {
+        let mut context = Context::new();
+        context.history.push("git commit".into()).unwrap();
+        context.history.push("ls".into()).unwrap();
+        context.history.push("git push".into()).unwrap();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+
+        simulate_keys!(map, [
+            Esc,
+            Char('/'),
+            Char('g'),
+            Char('i'),
+        ]);
+        // nothing is searched for until enter is pressed
+        assert_eq!(map.ed.current_history_location(), None);
+
+        simulate_keys!(map, [Char('\n')]);
+        assert!(!map.ed.is_searching());
+        assert_eq!(map.ed.current_history_location(), Some(2));
+        assert_eq!(map.ed.cursor(), 7);
+
+        simulate_keys!(map, [Char('n')]);
+        assert_eq!(map.ed.current_history_location(), Some(0));
+
+        // no older match, stay put
+        simulate_keys!(map, [Char('n')]);
+        assert_eq!(map.ed.current_history_location(), Some(0));
+
+        simulate_keys!(map, [Char('N')]);
+        assert_eq!(map.ed.current_history_location(), Some(2));
+    }
+
+    #[test]
+    /// search history forwards with '?'
+    fn search_history_forward() {
+        let mut context = Context::new();
+        context.history.push("git commit".into()).unwrap();
+        context.history.push("ls".into()).unwrap();
+        context.history.push("git push".into()).unwrap();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+
+        simulate_keys!(map, [
+            Esc,
+            Char('k'),
+            Char('k'),
+            Char('k'),
+            Char('?'),
+            Char('g'),
+            Char('\n'),
+        ]);
+        assert_eq!(map.ed.current_history_location(), Some(2));
+
+        // 'N' searches in the opposite direction
+        simulate_keys!(map, [Char('N')]);
+        assert_eq!(map.ed.current_history_location(), Some(0));
+    }
+
+    #[test]
+    /// an empty search query reuses the last query
+    fn search_history_empty_query() {
+        let mut context = Context::new();
+        context.history.push("git commit".into()).unwrap();
+        context.history.push("git push".into()).unwrap();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+
+        simulate_keys!(map, [
+            Esc,
+            Char('/'),
+            Char('g'),
+            Char('\n'),
+            Char('/'),
+            Char('\n'),
+        ]);
+        assert_eq!(map.ed.current_history_location(), Some(0));
+    }
+
+    #[test]
+    /// escape and backspace on an empty query cancel the search
+    fn search_history_cancel() {
+        let mut context = Context::new();
+        context.history.push("git commit".into()).unwrap();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor("new").unwrap();
+
+        simulate_keys!(map, [
+            Esc,
+            Char('/'),
+            Char('g'),
+            Esc,
+        ]);
+        assert!(!map.ed.is_searching());
+        assert_eq!(map.ed.current_history_location(), None);
+
+        simulate_keys!(map, [
+            Char('/'),
+            Char('g'),
+            Backspace,
+            Backspace,
+        ]);
+        assert!(!map.ed.is_searching());
+
+        // still in normal mode
+        simulate_keys!(map, [Char('x')]);
+        assert_eq!(String::from(map), "ne");
+    }
+
+    #[test]
+    /// a key sequence bound in insert mode can leave insert mode
+    fn bound_sequence_leaves_insert_mode() {
+        let mut context = Context::new();
+        context.key_binding_table.bind(
+            BindingMode::ViInsert,
+            &[Char('j'), Char('k')],
+            "vi-movement-mode",
+        );
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+
+        simulate_keys!(map, [
+            Char('a'),
+            Char('j'),
+            Char('b'),
+            Char('j'),
+            Char('k'),
+            Char('x'),
+        ]);
+        assert_eq!(String::from(map), "aj");
+    }
+
+    #[test]
+    fn yank_and_put() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor("abc def").unwrap();
+
+        simulate_keys!(map, [
+            Esc,
+            Char('0'),
+            Char('y'),
+            Char('w'),
+        ]);
+        assert_eq!(map.ed.cursor(), 0);
+        assert_eq!(map.ed.context().vi_registers.get(&'"').map(|s| &s[..]), Some("abc "));
+
+        simulate_keys!(map, [
+            Char('$'),
+            Char('p'),
+        ]);
+        assert_eq!(map.ed.cursor(), 10);
+
+        simulate_keys!(map, [
+            Char('0'),
+            Char('2'),
+            Char('P'),
+        ]);
+        assert_eq!(map.ed.cursor(), 7);
+        assert_eq!(String::from(map), "abc abc abc defabc ");
+    }
+
+    #[test]
+    /// yanking backwards moves the cursor to the start of the yanked text
+    fn yank_backwards() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor("abc def").unwrap();
+
+        simulate_keys!(map, [
+            Esc,
+            Char('y'),
+            Char('b'),
+        ]);
+        assert_eq!(map.ed.cursor(), 4);
+        assert_eq!(map.ed.context().vi_registers.get(&'"').map(|s| &s[..]), Some("de"));
+        assert_eq!(String::from(map), "abc def");
+    }
+
+    #[test]
+    fn yank_line() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor("abc").unwrap();
+
+        simulate_keys!(map, [
+            Esc,
+            Char('y'),
+            Char('y'),
+            Char('p'),
+        ]);
+        assert_eq!(String::from(map), "abcabc");
+    }
+
+    #[test]
+    /// deleted text goes into the unnamed register
+    fn delete_and_put() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor("abc def").unwrap();
+
+        simulate_keys!(map, [
+            Esc,
+            Char('0'),
+            Char('x'),
+            Char('p'),
+        ]);
+        assert_eq!(String::from(map.ed.current_buffer().clone()), "bac def");
+
+        simulate_keys!(map, [
+            Char('d'),
+            Char('d'),
+            Char('P'),
+        ]);
+        assert_eq!(String::from(map.ed.current_buffer().clone()), "bac def");
+
+        simulate_keys!(map, [
+            Char('0'),
+            Char('d'),
+            Char('t'),
+            Char('d'),
+            Char('$'),
+            Char('p'),
+        ]);
+        assert_eq!(String::from(map), "defbac ");
+    }
+
+    #[test]
+    fn named_registers() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor("abc").unwrap();
+
+        simulate_keys!(map, [
+            Esc,
+            Char('0'),
+            Char('"'),
+            Char('a'),
+            Char('y'),
+            Char('l'),
+            Char('l'),
+            Char('"'),
+            Char('A'),
+            Char('y'),
+            Char('l'),
+            Char('x'),
+        ]);
+        assert_eq!(map.ed.context().vi_registers.get(&'a').map(|s| &s[..]), Some("ab"));
+        assert_eq!(map.ed.context().vi_registers.get(&'"').map(|s| &s[..]), Some("b"));
+
+        simulate_keys!(map, [
+            Char('"'),
+            Char('a'),
+            Char('p'),
+            Char('p'),
+        ]);
+        assert_eq!(String::from(map), "acabb");
+    }
+
+    #[test]
+    /// repeating a put puts from the same register again
+    fn repeat_put() {
+        let mut context = Context::new();
+        context.vi_registers.insert('a', "x".into());
+        context.vi_registers.insert('"', "y".into());
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+
+        simulate_keys!(map, [
+            Esc,
+            Char('"'),
+            Char('a'),
+            Char('2'),
+            Char('p'),
+            Char('.'),
+            Char('3'),
+            Char('.'),
+            Char('p'),
+        ]);
+        assert_eq!(String::from(map), "xxxxxxxy");
+    }
+
+    #[test]
+    /// registers are kept in the context between lines
+    fn put_from_previous_line() {
+        let mut context = Context::new();
+        {
+            let out = Vec::new();
+            let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+            let mut map = Vi::new(ed);
+            map.ed.insert_str_after_cursor("abc").unwrap();
+            simulate_keys!(map, [
+                Esc,
+                Char('"'),
+                Char('q'),
+                Char('y'),
+                Char('y'),
+            ]);
+        }
+
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        simulate_keys!(map, [
+            Esc,
+            Char('"'),
+            Char('q'),
+            Char('p'),
+        ]);
+        assert_eq!(String::from(map), "abc");
+    }
+
+    #[test]
+    fn visual_delete() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor("abc def").unwrap();
+
+        simulate_keys!(map, [
+            Esc,
+            Char('0'),
+            Char('v'),
+            Char('e'),
+        ]);
+        assert_eq!(map.ed.selection(), Some((0, 3)));
+
+        simulate_keys!(map, [
+            Char('d'),
+        ]);
+        assert_eq!(map.ed.selection(), None);
+        assert_eq!(map.ed.cursor(), 0);
+        assert_eq!(map.ed.context().vi_registers.get(&'"').map(|s| &s[..]), Some("abc"));
+        assert_eq!(String::from(map), " def");
+    }
+
+    #[test]
+    fn visual_yank_backwards() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor("abc def").unwrap();
+
+        simulate_keys!(map, [
+            Esc,
+            Char('v'),
+            Char('b'),
+            Char('y'),
+        ]);
+        assert_eq!(map.ed.cursor(), 4);
+        assert_eq!(map.ed.context().vi_registers.get(&'"').map(|s| &s[..]), Some("def"));
+        assert_eq!(String::from(map), "abc def");
+    }
+
+    #[test]
+    fn visual_change() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor("abc def").unwrap();
+
+        simulate_keys!(map, [
+            Esc,
+            Char('0'),
+            Char('v'),
+            Char('2'),
+            Char('l'),
+            Char('c'),
+            Char('x'),
+            Char('y'),
+            Esc,
+        ]);
+        assert_eq!(map.mode(), Mode::Normal);
+        assert_eq!(String::from(map.ed.current_buffer().clone()), "xy def");
+
+        // the change is undone in one step
+        simulate_keys!(map, [
+            Char('u'),
+        ]);
+        assert_eq!(String::from(map), "abc def");
+    }
+
+    #[test]
+    fn visual_case() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor("Hello World").unwrap();
+
+        simulate_keys!(map, [
+            Esc,
+            Char('0'),
+            Char('v'),
+            Char('$'),
+            Char('~'),
+        ]);
+        assert_eq!(String::from(map.ed.current_buffer().clone()), "hELLO wORLD");
+
+        simulate_keys!(map, [
+            Char('v'),
+            Char('e'),
+            Char('U'),
+            Char('w'),
+            Char('v'),
+            Char('$'),
+            Char('u'),
+        ]);
+        assert_eq!(String::from(map.ed.current_buffer().clone()), "HELLO world");
+
+        simulate_keys!(map, [
+            Char('u'),
+        ]);
+        assert_eq!(String::from(map), "HELLO wORLD");
+    }
+
+    #[test]
+    fn visual_replace() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor("abc def").unwrap();
+
+        simulate_keys!(map, [
+            Esc,
+            Char('0'),
+            Char('v'),
+            Char('t'),
+            Char('e'),
+            Char('r'),
+            Char('x'),
+        ]);
+        assert_eq!(map.mode(), Mode::Normal);
+        assert_eq!(String::from(map), "xxxxxef");
+    }
+
+    #[test]
+    fn visual_escape() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor("abc").unwrap();
+
+        simulate_keys!(map, [
+            Esc,
+            Char('v'),
+            Char('h'),
+        ]);
+        assert_eq!(map.ed.selection(), Some((1, 3)));
+
+        simulate_keys!(map, [
+            Esc,
+        ]);
+        assert_eq!(map.mode(), Mode::Normal);
+        assert_eq!(map.ed.selection(), None);
+
+        simulate_keys!(map, [
+            Char('v'),
+            Char('v'),
+        ]);
+        assert_eq!(map.mode(), Mode::Normal);
+        assert_eq!(map.ed.selection(), None);
+    }
+
+    #[test]
+    fn visual_line_delete() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor("one\ntwo\nthree").unwrap();
+
+        simulate_keys!(map, [
+            Esc,
+        ]);
+        map.ed.move_cursor_to(5).unwrap();
+        simulate_keys!(map, [
+            Char('V'),
+        ]);
+        assert_eq!(map.ed.selection(), Some((4, 8)));
+
+        simulate_keys!(map, [
+            Char('d'),
+        ]);
+        assert_eq!(String::from(map), "one\nthree");
+    }
+
+    #[test]
+    fn change_inner_word() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor("foo bar baz").unwrap();
+
+        simulate_keys!(map, [
+            Esc,
+        ]);
+        map.ed.move_cursor_to(5).unwrap();
+        simulate_keys!(map, [
+            Char('c'),
+            Char('i'),
+            Char('w'),
+            Char('q'),
+            Char('u'),
+            Char('x'),
+            Esc,
+            Char('w'),
+            Char('.'),
+        ]);
+        assert_eq!(map.mode(), Mode::Normal);
+        assert_eq!(String::from(map), "foo qux qux");
+    }
+
+    #[test]
+    fn delete_around_word() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor("foo bar.baz qux").unwrap();
+
+        simulate_keys!(map, [
+            Esc,
+        ]);
+        map.ed.move_cursor_to(5).unwrap();
+        simulate_keys!(map, [
+            Char('d'),
+            Char('a'),
+            Char('W'),
+        ]);
+        assert_eq!(String::from(map.ed.current_buffer().clone()), "foo qux");
+
+        // the last word takes the whitespace before it
+        simulate_keys!(map, [
+            Char('d'),
+            Char('a'),
+            Char('w'),
+        ]);
+        assert_eq!(String::from(map), "foo");
+    }
+
+    #[test]
+    fn delete_inner_quotes() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor(r#"echo "a \"b\" c" done"#).unwrap();
+
+        simulate_keys!(map, [
+            Esc,
+        ]);
+        map.ed.move_cursor_to(10).unwrap();
+        simulate_keys!(map, [
+            Char('d'),
+            Char('i'),
+            Char('"'),
+        ]);
+        assert_eq!(map.ed.cursor(), 6);
+        assert_eq!(String::from(map), r#"echo "" done"#);
+    }
+
+    #[test]
+    fn change_around_quotes() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor("echo 'hello world' done").unwrap();
+
+        // the cursor is before the quotes, so the next quoted string is used
+        simulate_keys!(map, [
+            Esc,
+            Char('0'),
+            Char('c'),
+            Char('a'),
+            Char('\''),
+            Char('x'),
+            Char(' '),
+        ]);
+        assert_eq!(String::from(map), "echo x done");
+    }
+
+    #[test]
+    fn change_inner_brackets() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor("f(a, (b), c)").unwrap();
+
+        simulate_keys!(map, [
+            Esc,
+        ]);
+        map.ed.move_cursor_to(6).unwrap();
+        simulate_keys!(map, [
+            Char('c'),
+            Char('i'),
+            Char('('),
+            Char('x'),
+            Esc,
+        ]);
+        assert_eq!(String::from(map.ed.current_buffer().clone()), "f(a, (x), c)");
+
+        map.ed.move_cursor_to(2).unwrap();
+        simulate_keys!(map, [
+            Char('d'),
+            Char('a'),
+            Char('b'),
+        ]);
+        assert_eq!(String::from(map), "f");
+    }
+
+    #[test]
+    fn yank_inner_brackets() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor("a[b[c]d]e").unwrap();
+
+        // on the closing bracket, its own pair is used
+        simulate_keys!(map, [
+            Esc,
+            Char('h'),
+            Char('y'),
+            Char('i'),
+            Char(']'),
+            Char('$'),
+            Char('p'),
+        ]);
+        assert_eq!(map.ed.cursor(), 13);
+        assert_eq!(String::from(map), "a[b[c]d]eb[c]d");
+    }
+
+    #[test]
+    fn missing_text_object() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor("abc").unwrap();
+
+        simulate_keys!(map, [
+            Esc,
+            Char('d'),
+            Char('i'),
+            Char('('),
+        ]);
+        assert_eq!(map.mode(), Mode::Normal);
+        assert_eq!(String::from(map), "abc");
+    }
+
+    #[test]
+    fn visual_text_object() {
+        let mut context = Context::new();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor("foo bar baz").unwrap();
+
+        simulate_keys!(map, [
+            Esc,
+        ]);
+        map.ed.move_cursor_to(5).unwrap();
+        simulate_keys!(map, [
+            Char('V'),
+            Char('i'),
+            Char('w'),
+        ]);
+        assert_eq!(map.mode(), Mode::Visual { linewise: false });
+        assert_eq!(map.ed.selection(), Some((4, 7)));
+
+        simulate_keys!(map, [
+            Char('d'),
+        ]);
+        assert_eq!(String::from(map), "foo  baz");
+    }
+
+    #[test]
+    fn forward_word_accepts_autosuggestion() {
+        let mut context = Context::new();
+        context.history.push("git commit -m fix".into()).unwrap();
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+        map.ed.insert_str_after_cursor("git").unwrap();
+
+        simulate_keys!(map, [
+            Alt('f'),
+            Char('!'),
+        ]);
+        assert_eq!(map.mode(), Mode::Insert);
+        assert_eq!(String::from(map), "git commit!");
+    }
+
+    #[test]
+    fn menu_complete_escape() {
+        let mut context = Context::new();
+        context.completer = Some(Box::new(::BasicCompleter::new(vec!["bar", "baz"])));
+        let out = Vec::new();
+        let ed = Editor::new(out, "prompt".to_owned(), &mut context).unwrap();
+        let mut map = Vi::new(ed);
+
+        // escape closes the menu without leaving insert mode
+        simulate_keys!(map, [
+            Char('b'),
+            BackTab,
+            Esc,
+        ]);
+        assert_eq!(map.mode(), Mode::Insert);
+        simulate_keys!(map, [
+            BackTab,
+            Char('!'),
+        ]);
+        assert_eq!(String::from(map), "baz!");
+    }
 }
@@ -0,0 +1,151 @@
+use std::collections::{vec_deque, VecDeque};
+
+/// The default for `KillRing::max_size()`, the same as readline's.
+const DEFAULT_MAX_SIZE: usize = 10;
+
+/// Text removed by kill commands, such as `"kill-line"` and `"unix-word-rubout"`, which can be
+/// inserted again with `"yank"` and `"yank-pop"`.
+///
+/// The kill ring is kept in the `Context`, so text killed in one call to `read_line()` can be
+/// yanked in the next.
+#[derive(Debug, Clone)]
+pub struct KillRing {
+    // The newest kill is at the front.
+    kills: VecDeque<String>,
+    max_size: usize,
+    // Which kill was yanked last, counting from the newest.
+    yank_index: usize,
+}
+
+impl KillRing {
+    pub fn new() -> Self {
+        KillRing {
+            kills: VecDeque::new(),
+            max_size: DEFAULT_MAX_SIZE,
+            yank_index: 0,
+        }
+    }
+
+    /// Number of kills in the ring.
+    pub fn len(&self) -> usize {
+        self.kills.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kills.is_empty()
+    }
+
+    /// Maximum number of kills kept in the ring.
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Sets the maximum number of kills kept in the ring. Older kills are forgotten first.
+    pub fn set_max_size(&mut self, size: usize) {
+        self.max_size = size;
+        self.kills.truncate(size);
+        self.yank_index = 0;
+    }
+
+    /// Adds a new kill to the ring.
+    pub fn push<S: Into<String>>(&mut self, text: S) {
+        if self.max_size == 0 {
+            return;
+        }
+
+        self.kills.push_front(text.into());
+        self.kills.truncate(self.max_size);
+        self.yank_index = 0;
+    }
+
+    /// Adds `text` to the end of the newest kill.
+    pub fn append(&mut self, text: &str) {
+        match self.kills.front_mut() {
+            Some(kill) => kill.push_str(text),
+            None => return self.push(text),
+        }
+        self.yank_index = 0;
+    }
+
+    /// Adds `text` to the start of the newest kill.
+    pub fn prepend(&mut self, text: &str) {
+        match self.kills.front_mut() {
+            Some(kill) => kill.insert_str(0, text),
+            None => return self.push(text),
+        }
+        self.yank_index = 0;
+    }
+
+    /// Returns the newest kill.
+    pub fn yank(&mut self) -> Option<&str> {
+        self.yank_index = 0;
+        self.kills.front().map(|s| &s[..])
+    }
+
+    /// Returns the kill before the one last returned by `yank()` or `rotate()`. After the oldest
+    /// kill, this starts again from the newest.
+    pub fn rotate(&mut self) -> Option<&str> {
+        if self.kills.is_empty() {
+            return None;
+        }
+
+        self.yank_index = (self.yank_index + 1) % self.kills.len();
+        self.kills.get(self.yank_index).map(|s| &s[..])
+    }
+
+    /// Iterates over the kills, newest first.
+    pub fn iter(&self) -> vec_deque::Iter<String> {
+        self.kills.iter()
+    }
+}
+
+impl Default for KillRing {
+    fn default() -> Self {
+        KillRing::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_kills() {
+        let mut ring = KillRing::new();
+        ring.append("world");
+        ring.prepend("hello ");
+        ring.push("new");
+        ring.append("er");
+        assert_eq!(ring.iter().collect::<Vec<_>>(), vec!["newer", "hello world"]);
+    }
+
+    #[test]
+    fn yank_and_rotate() {
+        let mut ring = KillRing::new();
+        assert_eq!(ring.yank(), None);
+        assert_eq!(ring.rotate(), None);
+
+        ring.push("a");
+        ring.push("b");
+        ring.push("c");
+        assert_eq!(ring.yank(), Some("c"));
+        assert_eq!(ring.rotate(), Some("b"));
+        assert_eq!(ring.rotate(), Some("a"));
+        assert_eq!(ring.rotate(), Some("c"));
+        assert_eq!(ring.yank(), Some("c"));
+    }
+
+    #[test]
+    fn max_size() {
+        let mut ring = KillRing::new();
+        ring.set_max_size(2);
+        ring.push("a");
+        ring.push("b");
+        ring.push("c");
+        assert_eq!(ring.iter().collect::<Vec<_>>(), vec!["c", "b"]);
+
+        ring.set_max_size(0);
+        ring.push("d");
+        assert!(ring.is_empty());
+    }
+}
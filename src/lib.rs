@@ -1,4 +1,5 @@
 extern crate bytecount;
+extern crate libc;
 extern crate termion;
 extern crate unicode_width;
 
@@ -8,9 +9,21 @@ pub use event::*;
 mod editor;
 pub use editor::*;
 
+mod command;
+pub use command::*;
+
 mod complete;
 pub use complete::*;
 
+mod matching;
+pub use matching::*;
+
+mod combinators;
+pub use combinators::*;
+
+mod grammar;
+pub use grammar::*;
+
 mod context;
 pub use context::*;
 
@@ -20,9 +33,21 @@ pub use buffer::*;
 mod history;
 pub use history::*;
 
+mod highlight;
+pub use highlight::*;
+
+mod autosuggest;
+pub use autosuggest::*;
+
+mod kill_ring;
+pub use kill_ring::*;
+
 mod keymap;
 pub use keymap::*;
 
+mod inputrc;
+pub use inputrc::*;
+
 mod util;
 
 #[cfg(test)]
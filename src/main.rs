@@ -1,14 +1,23 @@
 extern crate liner;
 extern crate termion;
 
-use std::mem::replace;
 use std::env::{args, current_dir};
 use std::io;
 
-use liner::{Context, CursorPosition, Event, EventKind, FilenameCompleter};
+use liner::{CommandCompleter, Context, FilenameCompleter, PositionCompleter, PrefixCompleter};
 
 fn main() {
     let mut con = Context::new();
+    let cwd = current_dir().unwrap();
+    // Complete commands for the first word, unless it is a path, and filenames for the others.
+    let commands = PrefixCompleter::new()
+        .with_prefix("/", FilenameCompleter::new(Some(&cwd)))
+        .with_prefix(".", FilenameCompleter::new(Some(&cwd)))
+        .with_default(CommandCompleter::new().with_builtins(vec!["emacs", "vi", "exit"]));
+    let completer = PositionCompleter::new()
+        .with_index(0, commands)
+        .with_default(FilenameCompleter::new(Some(&cwd)));
+    con.completer = Some(Box::new(completer));
 
     let history_file = args().nth(1);
     match history_file {
@@ -22,28 +31,7 @@ fn main() {
     }
 
     loop {
-        let res = con.read_line("[prompt]$ ",
-                                &mut |Event { editor, kind }| {
-            if let EventKind::BeforeComplete = kind {
-                let (_, pos) = editor.get_words_and_cursor_position();
-
-                // Figure out of we are completing a command (the first word) or a filename.
-                let filename = match pos {
-                    CursorPosition::InWord(i) => i > 0,
-                    CursorPosition::InSpace(Some(_), _) => true,
-                    CursorPosition::InSpace(None, _) => false,
-                    CursorPosition::OnWordLeftEdge(i) => i >= 1,
-                    CursorPosition::OnWordRightEdge(i) => i >= 1,
-                };
-
-                if filename {
-                    let completer = FilenameCompleter::new(Some(current_dir().unwrap()));
-                    replace(&mut editor.context().completer, Some(Box::new(completer)));
-                } else {
-                    replace(&mut editor.context().completer, None);
-                }
-            }
-        });
+        let res = con.read_line("[prompt]$ ", &mut |_| {});
 
         match res {
             Ok(res) => {
@@ -0,0 +1,154 @@
+use Completion;
+
+/// How a completer matches its candidates against the word being completed. Each strategy scores
+/// the candidates it matches, so they can be listed best first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchStrategy {
+    /// The candidate starts with the word.
+    Prefix,
+    /// The candidate starts with the word, ignoring case. Candidates which match the case of the
+    /// word are ranked first.
+    CaseInsensitivePrefix,
+    /// The candidate contains the word. Candidates with the word nearer to the start are ranked
+    /// first.
+    Substring,
+    /// The candidate contains the characters of the word in order, ignoring case. Candidates with
+    /// the characters next to each other or at the start of words are ranked first, so `fb`
+    /// ranks `foo_bar` above `fooby`.
+    Fuzzy,
+}
+
+impl Default for MatchStrategy {
+    fn default() -> Self {
+        MatchStrategy::Prefix
+    }
+}
+
+impl MatchStrategy {
+    /// Returns how well `candidate` matches `word`, or None if it doesn't match. Higher scores
+    /// are better.
+    pub fn score(self, word: &str, candidate: &str) -> Option<i64> {
+        match self {
+            MatchStrategy::Prefix => if candidate.starts_with(word) {
+                Some(0)
+            } else {
+                None
+            },
+            MatchStrategy::CaseInsensitivePrefix => {
+                let mut exact_case = true;
+                let mut candidate_chars = candidate.chars();
+                for w in word.chars() {
+                    match candidate_chars.next() {
+                        Some(c) if c == w => {}
+                        Some(c) if eq_ignore_case(c, w) => exact_case = false,
+                        _ => return None,
+                    }
+                }
+                Some(if exact_case { 1 } else { 0 })
+            }
+            MatchStrategy::Substring => candidate
+                .find(word)
+                .map(|i| -(candidate[..i].chars().count() as i64)),
+            MatchStrategy::Fuzzy => fuzzy_score(word, candidate),
+        }
+    }
+
+    /// Keeps the completions whose text matches `word`, with their scores set, best first.
+    pub fn filter(self, word: &str, completions: Vec<Completion>) -> Vec<Completion> {
+        let mut res: Vec<Completion> = completions
+            .into_iter()
+            .filter_map(|c| self.score(word, &c.text).map(|score| c.with_score(score)))
+            .collect();
+        res.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.text.cmp(&b.text)));
+        res
+    }
+}
+
+fn eq_ignore_case(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+/// Matches the characters of `word` to the first characters in `candidate` which they can match,
+/// and scores the match by how many of them are consecutive, at the start of a word, or match
+/// case. Characters skipped in between lower the score.
+fn fuzzy_score(word: &str, candidate: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.chars().collect();
+    let mut score = 0;
+    let mut next = 0;
+
+    for w in word.chars() {
+        let i = match candidate[next..].iter().position(|&c| eq_ignore_case(c, w)) {
+            Some(offset) => next + offset,
+            None => return None,
+        };
+        let c = candidate[i];
+
+        if c == w {
+            score += 1;
+        }
+        if next > 0 && i == next {
+            score += 8;
+        } else if i == 0 || is_word_start(candidate[i - 1], c) {
+            score += 6;
+        }
+        score -= (i - next) as i64;
+        next = i + 1;
+    }
+
+    Some(score)
+}
+
+/// Whether `c` starts a word, when it comes after `prev`.
+fn is_word_start(prev: char, c: char) -> bool {
+    !prev.is_alphanumeric() || (prev.is_lowercase() && c.is_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranked(strategy: MatchStrategy, word: &str, candidates: &[&str]) -> Vec<String> {
+        let completions = candidates.iter().map(|&c| Completion::new(c)).collect();
+        strategy
+            .filter(word, completions)
+            .into_iter()
+            .map(|c| c.text)
+            .collect()
+    }
+
+    #[test]
+    fn prefix() {
+        let candidates = &["foo", "Foo", "afoo", "fo"];
+        assert_eq!(ranked(MatchStrategy::Prefix, "fo", candidates), vec!["fo", "foo"]);
+        assert_eq!(
+            ranked(MatchStrategy::CaseInsensitivePrefix, "fo", candidates),
+            vec!["fo", "foo", "Foo"]
+        );
+        assert_eq!(
+            ranked(MatchStrategy::CaseInsensitivePrefix, "\u{e9}", &["\u{c9}t\u{e9}"]),
+            vec!["\u{c9}t\u{e9}"]
+        );
+    }
+
+    #[test]
+    fn substring() {
+        assert_eq!(
+            ranked(MatchStrategy::Substring, "ar", &["bar", "art", "Art", "start", "a"]),
+            vec!["art", "bar", "start"]
+        );
+    }
+
+    #[test]
+    fn fuzzy() {
+        assert_eq!(
+            ranked(MatchStrategy::Fuzzy, "fb", &["fooby", "xfxb", "foo_bar", "bf", "FooBar"]),
+            vec!["foo_bar", "FooBar", "fooby", "xfxb"]
+        );
+        assert_eq!(
+            ranked(MatchStrategy::Fuzzy, "hist", &["history", "this", "h_i_s_t", "hoist"]),
+            vec!["history", "h_i_s_t", "hoist"]
+        );
+        assert_eq!(MatchStrategy::Fuzzy.score("", "abc"), Some(0));
+        assert_eq!(MatchStrategy::Fuzzy.score("abcd", "abc"), None);
+    }
+}
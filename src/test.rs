@@ -3,7 +3,8 @@ use context;
 
 use std::env;
 use std::fs;
-use std::io::{BufReader, BufRead, Write};
+use std::io::{BufReader, BufRead, Read, Write};
+use std::time::Duration;
 
 fn assert_cursor_pos(s: &str, cursor: usize, expected_pos: CursorPosition) {
     let buf = Buffer::from(s.to_owned());
@@ -29,13 +30,33 @@ fn test_get_cursor_position() {
                   ("abc  abc", 6, InWord(1)),
                   ("abc  abc", 8, OnWordRightEdge(1)),
                   (" a", 0, InSpace(None, Some(0))),
-                  ("a ", 2, InSpace(Some(0), None))];
+                  ("a ", 2, InSpace(Some(0), None)),
+                  ("a\\ b c", 4, OnWordRightEdge(0))];
 
     for t in tests {
         assert_cursor_pos(t.0, t.1, t.2);
     }
 }
 
+#[test]
+fn test_completion_context() {
+    let tests = &[("", 0, 0, ""),
+                  ("ls", 1, 0, "l"),
+                  ("ls ", 3, 1, ""),
+                  ("ls  abc", 4, 1, ""),
+                  ("ls abc", 5, 1, "ab"),
+                  ("ls abc", 3, 1, "")];
+
+    for &(s, cursor, index, word) in tests {
+        let buf = Buffer::from(s.to_owned());
+        let words = context::get_buffer_words(&buf);
+        let position = CursorPosition::get(cursor, &words);
+        let line = CompletionContext::new(&buf, cursor, &words, position);
+        assert_eq!(line.word_index(), index, "buffer: {:?}, cursor: {}", s, cursor);
+        assert_eq!(line.word(), word, "buffer: {:?}, cursor: {}", s, cursor);
+    }
+}
+
 fn assert_buffer_actions(start: &str, expected: &str, actions: &[Action]) {
     let mut buf = Buffer::from(start.to_owned());
     for a in actions {
@@ -133,3 +154,154 @@ fn test_reading_from_file() {
     assert_eq!(String::from(h.buffers[2].clone()), "c".to_string());
     assert_eq!(String::from(h.buffers[3].clone()), "d".to_string());
 }
+
+#[test]
+fn test_extended_history_file() {
+    let mut tmp_file = env::temp_dir();
+    tmp_file.push("liner_test_file789.txt");
+    let _ = fs::remove_file(&tmp_file);
+
+    let metadata = HistoryMetadata {
+        timestamp: Some(1500000000),
+        cwd: Some("/home/me/my\tdir".to_owned()),
+        exit_status: Some(-1),
+        session_id: Some("s1".to_owned()),
+        duration: Some(Duration::from_millis(1234)),
+    };
+    {
+        let mut h = History::new();
+        h.set_file_name(Some(tmp_file.to_string_lossy().into_owned()));
+        h.push(Buffer::from("plain")).unwrap();
+        h.set_file_format(HistoryFileFormat::Extended);
+        h.push_with_metadata(Buffer::from("echo a\\tb"), metadata.clone()).unwrap();
+        h.push(Buffer::from("ls")).unwrap();
+        assert_eq!(h.metadata(1), Some(&metadata));
+        assert!(h.metadata(2).unwrap().timestamp.is_some());
+        h.commit_history();
+    }
+
+    let mut h = History::new();
+    h.set_file_name(Some(tmp_file.to_string_lossy().into_owned()));
+    h.load_history().unwrap();
+    assert_eq!(h.len(), 3);
+    assert_eq!(String::from(h[0].clone()), "plain");
+    assert_eq!(h.metadata(0), Some(&HistoryMetadata::default()));
+    assert_eq!(String::from(h[1].clone()), "echo a\\tb");
+    assert_eq!(h.metadata(1), Some(&metadata));
+    assert_eq!(String::from(h[2].clone()), "ls");
+    assert!(h.metadata(2).unwrap().timestamp.is_some());
+    assert_eq!(h.metadata(2).unwrap().cwd, None);
+
+    fs::remove_file(tmp_file).unwrap();
+}
+
+#[test]
+fn test_multiline_history_file() {
+    let mut tmp_file = env::temp_dir();
+    tmp_file.push("liner_test_file012.txt");
+    let _ = fs::remove_file(&tmp_file);
+
+    let entries = ["echo a \\\nb", "one\ntwo\r\n", "#liner\tcmd=x", "back\\slash\\", "plain"];
+    {
+        let mut h = History::new();
+        h.set_file_name(Some(tmp_file.to_string_lossy().into_owned()));
+        for entry in &entries {
+            h.push(Buffer::from(*entry)).unwrap();
+        }
+        h.commit_history();
+    }
+
+    let f = fs::File::open(tmp_file.clone()).unwrap();
+    let lines: Vec<_> = BufReader::new(f).lines().map(|l| l.unwrap()).collect();
+    assert_eq!(lines.len(), entries.len());
+    assert_eq!(lines[3], "back\\slash\\");
+    assert_eq!(lines[4], "plain");
+
+    let mut h = History::new();
+    h.set_file_name(Some(tmp_file.to_string_lossy().into_owned()));
+    h.load_history().unwrap();
+    let loaded: Vec<_> = h.into_iter().map(|b| String::from(b.clone())).collect();
+    assert_eq!(loaded, entries);
+
+    fs::remove_file(tmp_file).unwrap();
+}
+
+#[test]
+fn test_shared_history_file() {
+    let mut tmp_file = env::temp_dir();
+    tmp_file.push("liner_test_file345.txt");
+    let _ = fs::remove_file(&tmp_file);
+    let file_name = Some(tmp_file.to_string_lossy().into_owned());
+
+    let mut a = History::new();
+    let mut b = History::new();
+    for h in [&mut a, &mut b].iter_mut() {
+        h.set_file_name(file_name.clone());
+        h.set_max_file_size(6);
+        h.share_history = true;
+    }
+
+    a.push(Buffer::from("a1")).unwrap();
+    b.push(Buffer::from("b1")).unwrap();
+    a.push(Buffer::from("a2")).unwrap();
+    assert_eq!(b.load_new_entries().unwrap(), 1);
+    assert_eq!(b.load_new_entries().unwrap(), 0);
+
+    // Entries read from the file are not added when they repeat the last entry, like pushed ones.
+    a.push(Buffer::from("x")).unwrap();
+    b.push(Buffer::from("x")).unwrap();
+    assert_eq!(a.load_new_entries().unwrap(), 0);
+
+    let entries = |h: &History| h.into_iter().map(|b| String::from(b.clone())).collect::<Vec<_>>();
+    assert_eq!(entries(&a), ["a1", "b1", "a2", "x"]);
+    assert_eq!(entries(&b), entries(&a));
+
+    b.push(Buffer::from("b2")).unwrap();
+    assert_eq!(a.load_new_entries().unwrap(), 1);
+
+    // Fill the file, so that it is replaced by one without the oldest entry. The other session
+    // then loads the whole file again.
+    b.push(Buffer::from("b3")).unwrap();
+    assert_eq!(a.load_new_entries().unwrap(), 5);
+    assert_eq!(entries(&a), ["b1", "a2", "x", "b2", "b3"]);
+    assert_eq!(entries(&b), ["a1", "b1", "a2", "x", "b2", "b3"]);
+
+    let f = fs::File::open(tmp_file.clone()).unwrap();
+    let lines: Vec<_> = BufReader::new(f).lines().map(|l| l.unwrap()).collect();
+    assert_eq!(lines, ["b1", "a2", "x", "x", "b2", "b3"]);
+
+    a.commit_history();
+    b.commit_history();
+    fs::remove_file(tmp_file).unwrap();
+}
+
+#[test]
+fn test_history_file_replaced_when_truncating() {
+    let dir = env::temp_dir().join("liner_test_dir567");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+    let tmp_file = dir.join("history");
+
+    let mut h = History::new();
+    h.set_file_name(Some(tmp_file.to_string_lossy().into_owned()));
+    h.set_max_file_size(2);
+    h.share_history = true;
+    h.push(Buffer::from("a")).unwrap();
+    h.push(Buffer::from("b")).unwrap();
+
+    // The full file is replaced by a new one rather than rewritten, so it stays whole for
+    // anyone still reading it.
+    let mut old = fs::File::open(&tmp_file).unwrap();
+    h.push(Buffer::from("c")).unwrap();
+    let mut contents = String::new();
+    old.read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "a\nb\n");
+
+    let f = fs::File::open(&tmp_file).unwrap();
+    let lines: Vec<_> = BufReader::new(f).lines().map(|l| l.unwrap()).collect();
+    assert_eq!(lines, ["b", "c"]);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    h.commit_history();
+    fs::remove_dir_all(dir).unwrap();
+}
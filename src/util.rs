@@ -5,6 +5,19 @@ pub fn width<S: AsRef<str>>(s: S) -> usize {
     remove_codes(s.as_ref()).width()
 }
 
+/// The longest start of `s` which takes up at most `max` columns on the terminal. `s` must not
+/// contain escape codes.
+pub fn truncate_to_width(s: &str, max: usize) -> &str {
+    let mut total = 0;
+    for (i, c) in s.char_indices() {
+        total += c.width().unwrap_or(0);
+        if total > max {
+            return &s[..i];
+        }
+    }
+    s
+}
+
 pub fn find_longest_common_prefix<T: Clone + Eq>(among: &[Vec<T>]) -> Option<Vec<T>> {
     if among.len() == 0 {
         return None;
@@ -49,30 +62,39 @@ pub enum AnsiState {
     Osc,
 }
 
+/// Moves the escape code parser `s` past `c`. Returns true if `c` is printed, rather than being
+/// part of an escape code.
+pub fn ansi_step(s: &mut AnsiState, c: char) -> bool {
+    match *s {
+        AnsiState::Norm => match c {
+            '\x1B' => *s = AnsiState::Esc,
+            _ => return true,
+        },
+        AnsiState::Esc => match c {
+            '[' => *s = AnsiState::Csi,
+            ']' => *s = AnsiState::Osc,
+            _ => *s = AnsiState::Norm,
+        },
+        AnsiState::Csi => match c {
+            'A' ... 'Z' | 'a' ... 'z' => *s = AnsiState::Norm,
+            _ => (),
+        },
+        AnsiState::Osc => match c {
+            '\x07' => *s = AnsiState::Norm,
+            _ => (),
+        }
+    }
+    false
+}
+
 pub fn remove_codes(input: &str) -> Cow<str> {
     if input.contains('\x1B') {
         let mut clean = String::new();
 
         let mut s = AnsiState::Norm;
         for c in input.chars() {
-            match s {
-                AnsiState::Norm => match c {
-                    '\x1B' => s = AnsiState::Esc,
-                    _ => clean.push(c),
-                },
-                AnsiState::Esc => match c {
-                    '[' => s = AnsiState::Csi,
-                    ']' => s = AnsiState::Osc,
-                    _ => s = AnsiState::Norm,
-                },
-                AnsiState::Csi => match c {
-                    'A' ... 'Z' | 'a' ... 'z' => s = AnsiState::Norm,
-                    _ => (),
-                },
-                AnsiState::Osc => match c {
-                    '\x07' => s = AnsiState::Norm,
-                    _ => (),
-                }
+            if ansi_step(&mut s, c) {
+                clean.push(c);
             }
         }
 
@@ -81,3 +103,16 @@ pub fn remove_codes(input: &str) -> Cow<str> {
         Cow::Borrowed(input)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate() {
+        assert_eq!(truncate_to_width("abc", 5), "abc");
+        assert_eq!(truncate_to_width("abc", 2), "ab");
+        assert_eq!(truncate_to_width("a\u{4e16}b", 2), "a");
+        assert_eq!(truncate_to_width("a\u{4e16}b", 3), "a\u{4e16}");
+    }
+}